@@ -0,0 +1,320 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+/*!
+Cells bound to a single-threaded executor rather than to an OS thread.
+
+An application running several single-threaded executors wants values created by one
+executor to be usable only by that executor. Binding them to the OS thread works until the
+executor is moved to another thread, for example while the application starts up. This
+module binds them to an [`AffinityDomain`] instead, which the executor enters around the
+code it runs:
+
+- [`ExecutorCell<T>`] is a [`crate::SendCell`] bound to the domain that is current when it
+  is created, and [`ExecutorFuture<T>`] is the matching [`crate::SendFuture`]. They have the
+  same checked accessors (`get`, `try_get`, `into_inner`, `into_future` and so on).
+- `SendCell::new` and `SendCell::builder` only create cells bound to an OS thread, so an
+  `ExecutorCell` is created with [`crate::SendCell::new_bound`], or with
+  [`crate::SendCell::builder_bound`] to give it its own violation policy.
+- The features that need an OS thread are not available: deferred drop
+  (`new_deferring_drop`), [`crate::ThreadToken`] access (`token`, `get_with`) and
+  `dispatch`.
+- A domain is entered with [`AffinityDomain::enter`], which returns a scoped guard. Code
+  outside any domain is bound to its OS thread, as with a plain `SendCell`.
+- Like any `SendCell`, an `ExecutorCell` must be dropped inside its domain, unless its
+  violation policy says otherwise.
+
+# Moving a domain to another thread
+
+A domain lives on the thread that first entered it, and entering it on another thread panics.
+Moving it is [`AffinityDomain::migrate`], which is `unsafe`: the values bound to the domain
+move along with it, while everything outside the domain stays behind. So they must not share
+non-Send state with anything outside the domain (an `Rc` whose clones are held elsewhere
+would have its reference count touched from two threads), nor depend on thread-local state
+of the old thread (as `MutexGuard`s and some FFI handles do). Migrating before any value is
+bound to the domain, at startup, is always fine.
+
+# Examples
+
+```rust
+use send_cells::{AffinityDomain, ExecutorCell};
+use std::rc::Rc;
+use std::sync::Arc;
+
+let domain = Arc::new(AffinityDomain::new());
+
+let cell = {
+    let _g = AffinityDomain::enter(&domain);
+    ExecutorCell::new_bound(Rc::new(42))
+};
+// Outside the domain, even on the same thread, the cell is off limits
+assert!(cell.try_get().is_err());
+
+std::thread::spawn(move || {
+    // SAFETY: the only value bound to the domain is an Rc that was never cloned, so nothing
+    // outside the domain shares its reference count, and it uses no thread-local state
+    unsafe { domain.migrate() };
+    let _g = AffinityDomain::enter(&domain);
+    assert_eq!(**cell.get(), 42);
+    // Drop the cell while the domain is still entered
+    drop(cell);
+})
+.join()
+.unwrap();
+```
+*/
+
+use crate::identity::{OsThread, ThreadIdentity};
+use crate::send_cell::{SendCell, SendFuture};
+use std::cell::Cell;
+use std::fmt::{Debug, Formatter};
+use std::marker::PhantomData;
+use std::num::NonZeroU64;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// A [`crate::SendCell`] bound to the current [`AffinityDomain`].
+///
+/// See the [module documentation](crate::affinity) for details.
+pub type ExecutorCell<T> = SendCell<T, AffinityId>;
+
+/// A [`crate::SendFuture`] bound to the current [`AffinityDomain`].
+///
+/// Created with [`crate::SendCell::into_future`] on an [`ExecutorCell`].
+pub type ExecutorFuture<T> = SendFuture<T, AffinityId>;
+
+thread_local! {
+    /// The domain entered on this thread, if any.
+    static CURRENT: Cell<Option<NonZeroU64>> = const { Cell::new(None) };
+}
+
+/// A context, such as a single-threaded executor, that [`ExecutorCell`]s can be bound to.
+///
+/// See the [module documentation](crate::affinity) for details.
+pub struct AffinityDomain {
+    id: NonZeroU64,
+    state: Mutex<DomainState>,
+}
+
+struct DomainState {
+    /// The thread the domain lives on, once it has been entered.
+    home: Option<NonZeroU64>,
+    /// How many guards are alive.
+    entered: usize,
+}
+
+impl AffinityDomain {
+    /// Creates a new domain, distinct from every other domain.
+    pub fn new() -> AffinityDomain {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        let id = NEXT.fetch_add(1, Ordering::Relaxed);
+        AffinityDomain {
+            id: NonZeroU64::new(id).expect("affinity domain counter overflowed"),
+            state: Mutex::new(DomainState {
+                home: None,
+                entered: 0,
+            }),
+        }
+    }
+
+    /// Makes `domain` the current domain of this thread until the guard is dropped.
+    ///
+    /// Domains can be nested, including re-entering the current one; the guards must be
+    /// dropped in reverse order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the domain lives on another thread; see [`Self::migrate`].
+    #[track_caller]
+    pub fn enter(domain: &AffinityDomain) -> AffinityGuard<'_> {
+        let token = crate::sys::current_thread_token();
+        {
+            let mut state = domain.state.lock().unwrap_or_else(PoisonError::into_inner);
+            match state.home {
+                Some(home) if home != token => {
+                    panic!("AffinityDomain entered on a thread other than its own; see `migrate`")
+                }
+                _ => state.home = Some(token),
+            }
+            state.entered += 1;
+        }
+        AffinityGuard {
+            domain,
+            previous: CURRENT.with(|current| current.replace(Some(domain.id))),
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Moves the domain to the current thread, so that it can be entered here.
+    ///
+    /// # Safety
+    ///
+    /// Every value bound to the domain moves to this thread with it, while everything
+    /// outside the domain stays where it is. So no value bound to the domain may:
+    /// - share non-Send state with anything outside the domain, such as an `Rc` with clones
+    ///   held outside it, or a reference into a thread-local;
+    /// - depend on thread-local state of the thread the domain lived on.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the domain is entered, on any thread.
+    pub unsafe fn migrate(&self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        assert!(
+            state.entered == 0,
+            "cannot migrate an AffinityDomain while it is entered"
+        );
+        state.home = Some(crate::sys::current_thread_token());
+    }
+}
+
+impl Default for AffinityDomain {
+    fn default() -> AffinityDomain {
+        AffinityDomain::new()
+    }
+}
+
+impl Debug for AffinityDomain {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AffinityDomain")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Keeps an [`AffinityDomain`] current on this thread; returned by [`AffinityDomain::enter`].
+#[must_use = "the domain is exited when the guard is dropped"]
+pub struct AffinityGuard<'a> {
+    domain: &'a AffinityDomain,
+    previous: Option<NonZeroU64>,
+    //the domain is entered on this thread only
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Debug for AffinityGuard<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AffinityGuard")
+            .field("domain", self.domain)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for AffinityGuard<'_> {
+    fn drop(&mut self) {
+        let current = CURRENT.with(|current| current.replace(self.previous));
+        //otherwise the domain would stay current while `migrate` considers it exited
+        assert_eq!(
+            current,
+            Some(self.domain.id),
+            "AffinityGuards must be dropped in reverse order"
+        );
+        let mut state = self
+            .domain
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        state.entered -= 1;
+    }
+}
+
+/// The [`ThreadIdentity`] of [`ExecutorCell`]: the current [`AffinityDomain`], or the OS
+/// thread outside of any domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AffinityId(Affinity);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Affinity {
+    Domain(NonZeroU64),
+    Thread(OsThread),
+}
+
+// SAFETY: a domain is only current on its home thread, and moving it requires `migrate`,
+// whose synchronization through the domain's mutex orders the old thread before the new one
+unsafe impl ThreadIdentity for AffinityId {
+    #[inline]
+    fn current() -> Self {
+        match CURRENT.with(Cell::get) {
+            Some(domain) => AffinityId(Affinity::Domain(domain)),
+            None => AffinityId(Affinity::Thread(OsThread::current())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_nested_domains() {
+        let outer = AffinityDomain::new();
+        let inner = AffinityDomain::new();
+        let unbound = ExecutorCell::new_bound(Rc::new(0));
+        let _outer = AffinityDomain::enter(&outer);
+        let in_outer = ExecutorCell::new_bound(Rc::new(1));
+        assert!(unbound.try_get().is_err());
+        {
+            let _inner = AffinityDomain::enter(&inner);
+            assert!(in_outer.try_get().is_err());
+            let _again = AffinityDomain::enter(&outer);
+            assert_eq!(**in_outer.get(), 1);
+        }
+        assert_eq!(**in_outer.get(), 1);
+        drop(_outer);
+        assert_eq!(**unbound.get(), 0);
+        //dropped in its domain
+        let _outer = AffinityDomain::enter(&outer);
+        drop(in_outer);
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_builder_policy() {
+        use crate::ViolationPolicy;
+
+        let domain = AffinityDomain::new();
+        let (cell, weak) = {
+            let _g = AffinityDomain::enter(&domain);
+            let value = Rc::new(1);
+            let weak = Rc::downgrade(&value);
+            let cell = ExecutorCell::builder_bound(value)
+                .policy(ViolationPolicy::LeakAndContinue)
+                .build();
+            (cell, weak)
+        };
+        //dropped outside the domain, so leaked instead of panicking
+        drop(cell);
+        assert_eq!(weak.strong_count(), 1);
+    }
+
+    #[test]
+    fn test_migrate() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        let domain = Arc::new(AffinityDomain::new());
+        let (cell, future) = {
+            let _g = AffinityDomain::enter(&domain);
+            let future = ExecutorCell::new_bound(async { Rc::new(2) }).into_future();
+            (ExecutorCell::new_bound(Rc::new(1)), future)
+        };
+
+        let other = domain.clone();
+        let entered_elsewhere = std::thread::spawn(move || {
+            std::panic::catch_unwind(|| drop(AffinityDomain::enter(&other))).is_err()
+        });
+        assert!(entered_elsewhere.join().unwrap());
+
+        std::thread::spawn(move || {
+            //safe because the bound Rcs were never cloned, so nothing outside the domain shares
+            //their reference counts, and they use no thread-local state
+            unsafe { domain.migrate() };
+            let _g = AffinityDomain::enter(&domain);
+            assert_eq!(**cell.get(), 1);
+            let mut future = Box::pin(future);
+            let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+            assert!(future.as_mut().poll(&mut context).is_ready());
+            drop(cell);
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -0,0 +1,482 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+/*!
+Running closures on a [`SendCell`]'s origin thread from any thread.
+
+A `SendCell` can only be accessed on the thread that created it. When the value is a
+thread-affine handle (a GUI object, an FFI context), other threads usually need to
+*send work* to it rather than touch it. This module provides that:
+
+- A thread that wants to receive work registers a [`ThreadDispatcher`] and periodically
+  calls [`ThreadDispatcher::pump`] (for example, from its run loop).
+- Any thread can then call [`SendCell::dispatch`] to queue a closure that runs on the
+  cell's origin thread with `&mut T`, and receives a [`DispatchHandle`] that can be
+  blocked on or awaited.
+
+The cell travels to the origin thread together with the closure and is handed back with
+the result, so no reference to the value ever outlives the call.
+
+# Examples
+
+```rust
+use send_cells::SendCell;
+use send_cells::dispatch::ThreadDispatcher;
+use std::rc::Rc;
+use std::sync::mpsc;
+
+let (to_main, from_owner) = mpsc::channel();
+let (to_owner, from_main) = mpsc::channel();
+let owner = std::thread::spawn(move || {
+    let dispatcher = ThreadDispatcher::register();
+    to_main.send(SendCell::new(Rc::new(41))).unwrap();
+    // A real application would pump from its run loop
+    while dispatcher.pump_timeout(std::time::Duration::from_secs(5)) == 0 {}
+    // The cell comes back to be dropped on the thread that created it
+    let cell: SendCell<Rc<i32>> = from_main.recv().unwrap();
+    assert_eq!(**cell.get(), 42);
+});
+
+let cell = from_owner.recv().unwrap();
+let handle = cell.dispatch(|value| {
+    *value = Rc::new(**value + 1);
+    **value
+}).ok().unwrap();
+let (cell, result) = handle.wait().unwrap();
+assert_eq!(result, 42);
+
+to_owner.send(cell).unwrap();
+owner.join().unwrap();
+```
+*/
+
+use crate::identity::{OsThread, ThreadIdentity};
+use crate::send_cell::SendCell;
+use std::any::Any;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Debug, Display, Formatter};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// The work queue of one registered thread.
+struct Queue {
+    state: Mutex<QueueState>,
+    available: Condvar,
+}
+
+struct QueueState {
+    jobs: VecDeque<Job>,
+    /// Set when the dispatcher is dropped; no further jobs are accepted.
+    closed: bool,
+}
+
+fn registry() -> &'static Mutex<HashMap<OsThread, Arc<Queue>>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<OsThread, Arc<Queue>>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Receives closures dispatched to the current thread.
+///
+/// Create one with [`ThreadDispatcher::register`] on the thread that owns the values, then
+/// call [`Self::pump`] or [`Self::pump_timeout`] to run queued closures. The dispatcher is
+/// bound to its thread and cannot be sent elsewhere.
+///
+/// Dropping the dispatcher unregisters the thread. Closures that are still queued are
+/// dropped without running (on this thread, so the cells they carry are dropped safely),
+/// and their handles report [`DispatchError::Cancelled`].
+pub struct ThreadDispatcher {
+    queue: Arc<Queue>,
+    thread: OsThread,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl ThreadDispatcher {
+    /// Registers a dispatcher for the current thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the current thread already has a registered dispatcher.
+    pub fn register() -> ThreadDispatcher {
+        let thread = OsThread::current();
+        let queue = Arc::new(Queue {
+            state: Mutex::new(QueueState {
+                jobs: VecDeque::new(),
+                closed: false,
+            }),
+            available: Condvar::new(),
+        });
+        //the lock is released before asserting, so a failed registration doesn't poison it
+        let registered = match registry().lock().unwrap().entry(thread) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(queue.clone());
+                true
+            }
+        };
+        assert!(
+            registered,
+            "A ThreadDispatcher is already registered for this thread"
+        );
+        ThreadDispatcher {
+            queue,
+            thread,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Runs every closure that is currently queued, without blocking.
+    ///
+    /// Returns the number of closures that ran.
+    pub fn pump(&self) -> usize {
+        let jobs = std::mem::take(&mut self.queue.state.lock().unwrap().jobs);
+        let count = jobs.len();
+        //run outside the lock, so closures can dispatch more work
+        for job in jobs {
+            job();
+        }
+        count
+    }
+
+    /// Waits up to `timeout` for work to arrive, then runs every queued closure.
+    ///
+    /// Returns the number of closures that ran, which is 0 if the timeout elapsed.
+    pub fn pump_timeout(&self, timeout: Duration) -> usize {
+        {
+            let state = self.queue.state.lock().unwrap();
+            let _state = self
+                .queue
+                .available
+                .wait_timeout_while(state, timeout, |state| state.jobs.is_empty())
+                .unwrap();
+        }
+        self.pump()
+    }
+}
+
+impl Debug for ThreadDispatcher {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ThreadDispatcher")
+            .field("thread", &self.thread)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for ThreadDispatcher {
+    fn drop(&mut self) {
+        registry().lock().unwrap().remove(&self.thread);
+        let jobs = {
+            let mut state = self.queue.state.lock().unwrap();
+            state.closed = true;
+            std::mem::take(&mut state.jobs)
+        };
+        //dropped here, on the owner thread
+        drop(jobs);
+    }
+}
+
+/// Why a dispatched closure did not produce a result.
+pub enum DispatchError {
+    /// The owner thread's dispatcher was dropped before the closure ran.
+    Cancelled,
+    /// The closure panicked; the payload is the value passed to `panic!`.
+    Panicked(Box<dyn Any + Send>),
+}
+
+impl Debug for DispatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DispatchError::Cancelled => f.write_str("Cancelled"),
+            DispatchError::Panicked(_) => f.debug_tuple("Panicked").finish_non_exhaustive(),
+        }
+    }
+}
+
+impl Display for DispatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DispatchError::Cancelled => {
+                f.write_str("the dispatcher was dropped before the closure ran")
+            }
+            DispatchError::Panicked(_) => f.write_str("the dispatched closure panicked"),
+        }
+    }
+}
+
+impl std::error::Error for DispatchError {}
+
+/// Shared state between a [`DispatchHandle`] and the job that completes it.
+struct Slot<O> {
+    state: Mutex<SlotState<O>>,
+    done: Condvar,
+}
+
+struct SlotState<O> {
+    result: Option<Result<O, DispatchError>>,
+    waker: Option<Waker>,
+}
+
+impl<O> Slot<O> {
+    fn complete(&self, result: Result<O, DispatchError>) {
+        let waker = {
+            let mut state = self.state.lock().unwrap();
+            state.result = Some(result);
+            state.waker.take()
+        };
+        self.done.notify_all();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Completes the slot when the job runs, or with `Cancelled` if the job is dropped unrun.
+struct Completer<O> {
+    slot: Option<Arc<Slot<O>>>,
+}
+
+impl<O> Completer<O> {
+    fn complete(mut self, result: Result<O, DispatchError>) {
+        self.slot.take().unwrap().complete(result);
+    }
+}
+
+impl<O> Drop for Completer<O> {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot.take() {
+            slot.complete(Err(DispatchError::Cancelled));
+        }
+    }
+}
+
+/// A handle to a closure dispatched with [`SendCell::dispatch`].
+///
+/// On success, the handle yields the cell (still bound to its origin thread) together
+/// with the closure's result. It can be waited on with [`Self::wait`], or awaited.
+#[must_use = "the cell is returned through the handle"]
+pub struct DispatchHandle<T, R> {
+    slot: Arc<Slot<(SendCell<T>, R)>>,
+}
+
+impl<T, R> DispatchHandle<T, R> {
+    /// Blocks the current thread until the closure has run.
+    ///
+    /// Must not be called on the owner thread unless the closure already ran, since the
+    /// owner thread would be blocked instead of pumping its dispatcher. Closures
+    /// dispatched from the owner thread itself run immediately, so their handles never
+    /// block.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DispatchError`] if the closure panicked or was cancelled.
+    pub fn wait(self) -> Result<(SendCell<T>, R), DispatchError> {
+        let state = self.slot.state.lock().unwrap();
+        let mut state = self
+            .slot
+            .done
+            .wait_while(state, |state| state.result.is_none())
+            .unwrap();
+        state.result.take().unwrap()
+    }
+
+    /// Returns `true` if the closure has finished (or will never run).
+    pub fn is_finished(&self) -> bool {
+        self.slot.state.lock().unwrap().result.is_some()
+    }
+}
+
+impl<T, R> Debug for DispatchHandle<T, R> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DispatchHandle")
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+impl<T, R> Future for DispatchHandle<T, R> {
+    type Output = Result<(SendCell<T>, R), DispatchError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.slot.state.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Runs `f` against the cell on `owner`, which must be the cell's origin thread.
+///
+/// Runs immediately if the current thread is the owner; otherwise queues the closure on
+/// the owner's dispatcher, or gives the cell back if there is none.
+pub(crate) fn dispatch<T, R, F>(
+    owner: OsThread,
+    cell: SendCell<T>,
+    f: F,
+) -> Result<DispatchHandle<T, R>, SendCell<T>>
+where
+    T: 'static,
+    R: Send + 'static,
+    F: FnOnce(&mut T) -> R + Send + 'static,
+{
+    let slot = Arc::new(Slot {
+        state: Mutex::new(SlotState {
+            result: None,
+            waker: None,
+        }),
+        done: Condvar::new(),
+    });
+
+    if OsThread::current() == owner {
+        job(cell, f, slot.clone())();
+        return Ok(DispatchHandle { slot });
+    }
+
+    let queue = registry().lock().unwrap().get(&owner).cloned();
+    let Some(queue) = queue else {
+        return Err(cell);
+    };
+    let mut state = queue.state.lock().unwrap();
+    //checked under the queue lock, so a job can't be queued after the dispatcher drains
+    if state.closed {
+        return Err(cell);
+    }
+    state.jobs.push_back(Box::new(job(cell, f, slot.clone())));
+    drop(state);
+    queue.available.notify_one();
+    Ok(DispatchHandle { slot })
+}
+
+/// Builds the closure that runs `f` on the owner thread and completes `slot`.
+fn job<T, R, F>(
+    cell: SendCell<T>,
+    f: F,
+    slot: Arc<Slot<(SendCell<T>, R)>>,
+) -> impl FnOnce() + Send + 'static
+where
+    T: 'static,
+    R: Send + 'static,
+    F: FnOnce(&mut T) -> R + Send + 'static,
+{
+    let completer = Completer { slot: Some(slot) };
+    move || {
+        let mut cell = cell;
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| f(cell.get_mut())));
+        completer.complete(match result {
+            Ok(r) => Ok((cell, r)),
+            Err(payload) => Err(DispatchError::Panicked(payload)),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use std::sync::mpsc;
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_dispatch_on_origin_thread_runs_immediately() {
+        let cell = SendCell::new(Rc::new(1));
+        let handle = cell.dispatch(|rc| **rc * 2).ok().unwrap();
+        assert!(handle.is_finished());
+        let (cell, result) = handle.wait().unwrap();
+        assert_eq!(result, 2);
+        assert_eq!(**cell.get(), 1);
+    }
+
+    #[test]
+    fn test_dispatch_from_other_thread() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        let dispatcher = ThreadDispatcher::register();
+        let cell = SendCell::new(Rc::new(41));
+        let owner = std::thread::current().id();
+
+        let worker = std::thread::spawn(move || {
+            let handle = cell
+                .dispatch(move |rc| {
+                    assert_eq!(std::thread::current().id(), owner);
+                    *rc = Rc::new(**rc + 1);
+                    **rc
+                })
+                .ok()
+                .unwrap();
+            let (cell, result) = handle.wait().unwrap();
+            (cell, result)
+        });
+
+        while dispatcher.pump_timeout(Duration::from_secs(5)) == 0 {}
+        let (cell, result) = worker.join().unwrap();
+        assert_eq!(result, 42);
+        assert_eq!(**cell.get(), 42);
+    }
+
+    #[test]
+    fn test_dispatch_without_dispatcher_returns_cell() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        let cell = SendCell::new(Rc::new(7));
+        let cell = std::thread::spawn(move || cell.dispatch(|rc| **rc).unwrap_err())
+            .join()
+            .unwrap();
+        assert_eq!(**cell.get(), 7);
+    }
+
+    #[test]
+    fn test_dispatch_panic_is_reported() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        let dispatcher = ThreadDispatcher::register();
+        let cell = SendCell::new(Rc::new(0));
+        let handle =
+            std::thread::spawn(move || cell.dispatch(|_| -> i32 { panic!("boom") }).ok().unwrap())
+                .join()
+                .unwrap();
+        assert_eq!(dispatcher.pump(), 1);
+        match handle.wait() {
+            Err(DispatchError::Panicked(payload)) => {
+                assert_eq!(*payload.downcast::<&str>().unwrap(), "boom");
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_dropping_dispatcher_cancels_queued_work() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        let (cell_tx, cell_rx) = mpsc::channel();
+        let (queued_tx, queued_rx) = mpsc::channel();
+        let owner = std::thread::spawn(move || {
+            let dispatcher = ThreadDispatcher::register();
+            cell_tx.send(SendCell::new(Rc::new(1))).unwrap();
+            queued_rx.recv().unwrap();
+            //dropped without pumping; the queued cell is dropped here, on its origin thread
+            drop(dispatcher);
+        });
+
+        let cell = cell_rx.recv().unwrap();
+        let handle = cell.dispatch(|rc| **rc).ok().unwrap();
+        queued_tx.send(()).unwrap();
+        assert!(matches!(handle.wait(), Err(DispatchError::Cancelled)));
+        owner.join().unwrap();
+    }
+
+    #[test]
+    fn test_register_twice_panics() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        std::thread::spawn(|| {
+            let _first = ThreadDispatcher::register();
+            let second = std::panic::catch_unwind(ThreadDispatcher::register);
+            assert!(second.is_err());
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+/*!
+Pluggable notions of "the current execution context" for [`crate::SendCell`].
+
+By default, a [`crate::SendCell`] is bound to the OS thread that created it. Green-thread
+runtimes, fibers and some embedded schedulers have their own notion of the current context,
+which may move between OS threads, or share one OS thread with other contexts. Implementing
+[`ThreadIdentity`] for such a context lets a `SendCell<T, I>` or `SendFuture<T, I>` be bound
+to it instead.
+
+[`OsThread`] is the default identity, and the only one supported by the features that need
+an actual OS thread to run on, such as deferred drop and dispatch.
+
+# Examples
+
+```rust
+use send_cells::{SendCell, ThreadIdentity};
+use std::cell::Cell;
+use std::rc::Rc;
+
+thread_local! {
+    static CURRENT_FIBER: Cell<u32> = const { Cell::new(0) };
+}
+
+/// The fiber currently running on this thread.
+///
+/// This toy scheduler never moves a fiber to another thread; a real one would also
+/// synchronize when it does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fiber(std::thread::ThreadId, u32);
+
+// SAFETY: two fibers never run at once on one thread, and never move between threads
+unsafe impl ThreadIdentity for Fiber {
+    fn current() -> Self {
+        Fiber(std::thread::current().id(), CURRENT_FIBER.with(Cell::get))
+    }
+}
+
+let cell = SendCell::<_, Fiber>::new_bound(Rc::new(1));
+assert_eq!(**cell.get(), 1);
+
+// Same OS thread, different fiber
+CURRENT_FIBER.with(|fiber| fiber.set(1));
+assert!(cell.try_get().is_err());
+
+// Back in the fiber that owns the cell, so it can be dropped
+CURRENT_FIBER.with(|fiber| fiber.set(0));
+```
+*/
+
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::num::NonZeroU64;
+
+/// Identifies the execution context that checked cells are bound to.
+///
+/// A cell records `Self::current()` when it is created, and compares it with
+/// `Self::current()` on every access.
+///
+/// # Safety
+///
+/// Checked cells hand out their non-Send values whenever the identities compare equal, so
+/// implementations must guarantee that:
+/// - two calls to `current()` return equal values only if they are made from the same
+///   context, and a context never runs on more than one OS thread at a time;
+/// - whenever a context moves from one OS thread to another, everything it did on the first
+///   thread happens before anything it does on the second (as with any scheduler that hands
+///   work over through a mutex or a channel);
+/// - values bound to the context do not depend on thread-local state of the OS thread,
+///   unless the context never moves between OS threads.
+///
+/// `Clone` and `Eq` must not panic, since they are used in `Drop`.
+pub unsafe trait ThreadIdentity: Clone + Eq + Debug + Send + Sync + 'static {
+    /// Returns the identity of the context the caller is running in.
+    fn current() -> Self;
+}
+
+/// The current OS thread, the default [`ThreadIdentity`].
+///
+/// Backed by [`crate::sys::current_thread_token`], so that checks are a thread-local read and
+/// an integer comparison.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OsThread(pub(crate) NonZeroU64);
+
+// SAFETY: tokens are unique per thread and never reused
+unsafe impl ThreadIdentity for OsThread {
+    #[inline]
+    fn current() -> Self {
+        OsThread(crate::sys::current_thread_token())
+    }
+}
+
+/// Proof that the holder is running on a particular OS thread.
+///
+/// A token is `!Send`, so it never leaves the thread it was created on. Passing it to
+/// [`crate::SendCell::get_with`] replaces the cell's thread check with a comparison against
+/// the token, so that one thread-local read can be amortized over many accesses, to any
+/// number of cells.
+///
+/// A token is a cheaper check, not a proof that removes the check. It is not zero-sized: it
+/// records which thread it was created on, and `get_with` still compares that with the
+/// cell's origin. A zero-sized token could only prove that it is on *its own* thread, which
+/// is always the current one, and so could not tell whether a cell was created there. The
+/// comparison is an integer comparison, which is what remains after the thread-local read is
+/// hoisted out of the loop.
+///
+/// # Examples
+///
+/// ```rust
+/// use send_cells::{SendCell, ThreadToken};
+/// use std::rc::Rc;
+///
+/// let cells: Vec<_> = (0..100).map(|i| SendCell::new(Rc::new(i))).collect();
+/// let token = ThreadToken::current();
+/// let sum: i32 = cells.iter().map(|cell| **cell.get_with(&token)).sum();
+/// assert_eq!(sum, 4950);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThreadToken {
+    pub(crate) thread: OsThread,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl ThreadToken {
+    /// Returns a token for the current thread.
+    #[inline]
+    pub fn current() -> ThreadToken {
+        ThreadToken {
+            thread: OsThread::current(),
+            _not_send: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_os_thread_identity() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        let here = OsThread::current();
+        assert_eq!(here, OsThread::current());
+        let there = std::thread::spawn(OsThread::current).join().unwrap();
+        assert_ne!(here, there);
+    }
+}
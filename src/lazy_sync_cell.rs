@@ -0,0 +1,196 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+/*!
+A [`crate::SyncCell`] whose value is created on first access.
+
+This module provides [`LazySyncCell<T, F>`], the `SyncCell` counterpart of
+[`std::sync::LazyLock`]. Like `SyncCell`, it only requires `T: Send` to be shared between
+threads, so it can hold non-Sync values such as FFI handles in a `static`. The initializer
+runs under the cell's own lock the first time any thread accesses the value.
+
+# Panics
+
+If the initializer panics, the cell is poisoned and every later access panics, as with
+`LazyLock`. Accessing the cell from inside its own initializer or closures panics instead
+of deadlocking.
+
+# Examples
+
+```rust
+use send_cells::LazySyncCell;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// A global registry of non-Sync values
+static REGISTRY: LazySyncCell<HashMap<u32, RefCell<String>>> = LazySyncCell::new(HashMap::new);
+
+REGISTRY.with_mut(|map| map.insert(1, RefCell::new(String::from("device"))));
+REGISTRY.with(|map| map[&1].borrow_mut().push_str(" #1"));
+assert_eq!(REGISTRY.with(|map| map[&1].borrow().clone()), "device #1");
+```
+*/
+
+use crate::sync_cell::SyncCell;
+use std::fmt::{Debug, Formatter};
+
+enum State<T, F> {
+    Uninit(F),
+    Init(T),
+    /// The initializer panicked.
+    Poisoned,
+}
+
+/// A thread-safe cell for a non-Sync value that is initialized on first access.
+///
+/// See the [module documentation](crate::lazy_sync_cell) for details.
+pub struct LazySyncCell<T, F = fn() -> T> {
+    state: SyncCell<State<T, F>>,
+}
+
+impl<T, F: FnOnce() -> T> LazySyncCell<T, F> {
+    /// Creates a new cell that will be initialized with `init` on first access.
+    #[inline]
+    #[track_caller]
+    pub const fn new(init: F) -> LazySyncCell<T, F> {
+        LazySyncCell {
+            state: SyncCell::new(State::Uninit(init)),
+        }
+    }
+
+    /// Accesses the value through a closure, initializing it first if needed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the initializer panicked (now or on an earlier access), if a previous
+    /// closure panicked, or if called from inside the initializer or a closure on this cell.
+    #[inline]
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        self.state.with_mut(|state| f(force(state)))
+    }
+
+    /// Accesses the value mutably through a closure, initializing it first if needed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the initializer panicked (now or on an earlier access), if a previous
+    /// closure panicked, or if called from inside the initializer or a closure on this cell.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::LazySyncCell;
+    ///
+    /// let cell = LazySyncCell::new(|| vec![1]);
+    /// cell.with_mut(|v| v.push(2));
+    /// assert_eq!(cell.with(|v| v.len()), 2);
+    /// ```
+    #[inline]
+    pub fn with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        self.state.with_mut(|state| f(force(state)))
+    }
+
+    /// Returns `true` if the value has been initialized.
+    #[inline]
+    pub fn is_initialized(&self) -> bool {
+        self.state.with(|state| matches!(state, State::Init(_)))
+    }
+
+    /// Consumes the cell and returns the value, or the initializer if it never ran.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the initializer panicked.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::LazySyncCell;
+    ///
+    /// let cell = LazySyncCell::new(|| 5);
+    /// assert!(cell.into_inner().is_err());
+    ///
+    /// let cell = LazySyncCell::new(|| 5);
+    /// cell.with(|_| ());
+    /// assert_eq!(cell.into_inner().ok(), Some(5));
+    /// ```
+    pub fn into_inner(self) -> Result<T, F> {
+        match self.state.into_inner() {
+            State::Init(value) => Ok(value),
+            State::Uninit(init) => Err(init),
+            State::Poisoned => poisoned(),
+        }
+    }
+}
+
+/// Runs the initializer if it has not run yet, and returns the value.
+fn force<T, F: FnOnce() -> T>(state: &mut State<T, F>) -> &mut T {
+    if let State::Uninit(_) = state {
+        //leave the cell poisoned if `init` panics
+        let State::Uninit(init) = std::mem::replace(state, State::Poisoned) else {
+            unreachable!()
+        };
+        *state = State::Init(init());
+    }
+    match state {
+        State::Init(value) => value,
+        State::Uninit(_) => unreachable!(),
+        State::Poisoned => poisoned(),
+    }
+}
+
+#[cold]
+fn poisoned() -> ! {
+    panic!("LazySyncCell instance has previously been poisoned")
+}
+
+impl<T: Debug, F> Debug for LazySyncCell<T, F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut d = f.debug_tuple("LazySyncCell");
+        self.state.with(|state| match state {
+            State::Init(value) => d.field(value).finish(),
+            State::Uninit(_) => d.field(&format_args!("<uninit>")).finish(),
+            State::Poisoned => d.field(&format_args!("<poisoned>")).finish(),
+        })
+    }
+}
+
+impl<T: Default> Default for LazySyncCell<T> {
+    #[track_caller]
+    fn default() -> LazySyncCell<T> {
+        LazySyncCell::new(T::default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_initialized_on_first_access() {
+        let runs = std::cell::Cell::new(0);
+        let cell = LazySyncCell::new(|| {
+            runs.set(runs.get() + 1);
+            Rc::new(7)
+        });
+        assert!(!cell.is_initialized());
+        assert_eq!(format!("{cell:?}"), "LazySyncCell(<uninit>)");
+        assert_eq!(cell.with(|rc| **rc), 7);
+        cell.with_mut(|rc| *rc = Rc::new(8));
+        assert_eq!(cell.with(|rc| **rc), 8);
+        assert_eq!(runs.get(), 1);
+        assert_eq!(format!("{cell:?}"), "LazySyncCell(8)");
+    }
+
+    #[test]
+    //note: unwind tests are not supported in wasm
+    fn test_panicking_initializer_poisons() {
+        let cell: LazySyncCell<i32> = LazySyncCell::new(|| panic!("init failed"));
+        let first = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| cell.with(|v| *v)));
+        assert!(first.is_err());
+        let second = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| cell.with(|v| *v)));
+        assert!(second.is_err());
+        let inner = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| cell.into_inner()));
+        assert!(inner.is_err());
+    }
+}
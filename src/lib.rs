@@ -55,21 +55,76 @@ Safe wrappers provide runtime-checked access to wrapped values:
 Allows sending non-Send types between threads with runtime thread checking:
 - Remembers the thread it was created on
 - Panics if accessed from a different thread
+- Can defer a wrong-thread drop to its origin thread instead of panicking
+- Wrong-thread handling is configurable through a [`ViolationPolicy`]
+- Can be bound to a fiber or executor instead of an OS thread, via a custom [`ThreadIdentity`]
+- Can run closures on its origin thread from any thread, via a [`dispatch::ThreadDispatcher`]
 - Perfect for single-threaded async contexts
 
+## [`StickyCell<T>`]
+
+Like `SendCell`, but the value is kept in its origin thread's local storage:
+- The cell only holds a key, so it is `Send + Sync`
+- Can be dropped on any thread without panicking
+- A value dropped elsewhere is released when its origin thread exits
+
 ## [`SyncCell<T>`]
 
 Allows sharing non-Sync types between threads with mutex-based synchronization:
 - Uses internal mutex for thread-safe access
 - Closure-based API prevents holding locks across await points
+- Non-blocking and timed access with `try_with` and `with_timeout`
+- `with_async` waits for the lock without blocking executor threads
+- Poisoning can be inspected, cleared, bypassed, or disabled
+- Same-thread reentry panics instead of deadlocking (`ReentrantSyncCell` allows nested reads)
+- `sync_cell::with_all` locks several cells at once in a deadlock-free order
+- `Cell`-style helpers: `replace`, `set`, `take`, `swap`, `update`, `get_cloned`, `get_copy`
+- `const fn new`, so a `SyncCell` can be placed directly in a `static`
+- `wait_until` with `notify_one`/`notify_all` makes the cell a condition variable
 - Ideal for shared state in multi-threaded applications
 
+## [`LazySyncCell<T>`] and [`OnceSyncCell<T>`]
+
+Static-friendly `SyncCell`s whose value is created on first use:
+- `LazySyncCell` runs its initializer on first access, like `LazyLock`
+- `OnceSyncCell` is set at most once, like `OnceLock`
+- Only require `T: Send`, so they can hold non-Sync values in a global registry
+
+## [`RwSyncCell<T>`]
+
+A reader-writer variant of `SyncCell` for read-heavy shared state:
+- `with` takes a shared lock, so readers run concurrently
+- `with_mut` takes an exclusive lock
+- Requires `T: Send + Sync` to be shared between threads
+
+## [`ThreadBoundSyncCell<T>`]
+
+The `Sync` counterpart of `SendCell`, for sharing handles to non-Send values:
+- `Send + Sync` for any `T`, so it can be shared in an `Arc`
+- Closure-based access, permitted only on the thread that created the cell
+- Ideal for values that are shared widely but only touched at home
+
+## [`MainThreadCell<T>`]
+
+For values that belong to the main thread, such as UI or FFI objects:
+- `Send + Sync` for any `T`, and only accessible with a zero-sized `MainThreadMarker`
+- A marker can only be obtained on the main thread, so access needs no runtime check
+- The main thread is recognized automatically on Linux, or set with `sys::mark_main_thread`
+
 ## [`SendFuture<T>`]
 
 Wraps non-Send futures to make them Send:
-- Runtime checks ensure the future is only polled on the correct thread
+- Runtime checks ensure the future is only polled and dropped on the correct thread
 - Enables use of non-Send futures with thread pool executors
 
+## [`ExecutorCell<T>`] and [`ExecutorFuture<T>`]
+
+`SendCell` and `SendFuture` bound to a single-threaded executor instead of an OS thread:
+- The executor enters an `AffinityDomain` around the code it runs, via a scoped guard
+- Values created inside the domain are only accessible inside it
+- The domain can be moved to another OS thread, for example while the application starts up
+- Created with `SendCell::new_bound` or `SendCell::builder_bound`; deferred drop, `ThreadToken` access and dispatch need an OS thread and are not available
+
 # Unsafe Wrappers
 
 Unsafe wrappers provide zero-cost abstractions when you can manually verify safety:
@@ -93,10 +148,23 @@ Wraps non-Send futures without runtime checks:
 |------|----------|------------|--------|
 | `SendCell` | Moving non-Send types in async contexts | Good | Runtime checked |
 | `SyncCell` | Sharing non-Sync types between threads | Good | Mutex protected |
+| `RwSyncCell` | Read-heavy shared state | Good | RwLock protected |
+| `LazySyncCell` / `OnceSyncCell` | Non-Sync values in a `static` | Good | Mutex protected |
+| `StickyCell` | Non-Send values that may be dropped on any thread | Good | Runtime checked |
+| `ThreadBoundSyncCell` | Sharing handles to non-Send values | Good | Runtime checked |
+| `MainThreadCell` | Main-thread-only UI or FFI values | Best | Checked once, by `MainThreadMarker` |
 | `SendFuture` | Using non-Send futures with Send requirements | Good | Runtime checked |
+| `ExecutorCell` / `ExecutorFuture` | Values owned by one executor | Good | Runtime checked |
 | `UnsafeSendCell` | Platform guarantees thread safety | Best | Manual verification |
 | `UnsafeSendFuture` | Maximum performance for futures | Best | Manual verification |
 
+# Cargo Features
+
+- `backtrace`: capture a backtrace when each `SendCell` is created, and include it in
+  wrong-thread panic messages and violation reports
+- `deadlock-detection`: in debug builds, panic (or call a hook) the first time two
+  `SyncCell`s are locked in inconsistent orders; see the `lock_order` module
+
 # Platform Support
 
 ## Standard Platforms
@@ -211,13 +279,14 @@ and can rigorously verify thread safety.
 
 ## Runtime Overhead
 
-- **Safe wrappers**: Small overhead for thread ID checking or mutex operations
+- **Safe wrappers**: Small overhead for thread checks (a thread-local read, an integer comparison, and an atomic load to notice deferred drops) or mutex operations
+- **Hot loops**: `SendCell::get_with` checks against a [`ThreadToken`] instead, skipping the thread-local read
 - **Unsafe wrappers**: Zero runtime overhead
 
 ## Memory Overhead
 
-- **SendCell**: One `ThreadId` + wrapped value
-- **SyncCell**: One `Mutex<()>` + wrapped value
+- **SendCell**: A thread token, the creation site (for diagnostics), and drop settings + wrapped value
+- **SyncCell**: An internal lock (a small `Mutex` and two `Condvar`s) + wrapped value
 - **UnsafeSendCell**: No overhead (transparent wrapper)
 
 # Related Crates
@@ -226,12 +295,41 @@ and can rigorously verify thread safety.
 - [once_cell](https://crates.io/crates/once_cell) - Lazy initialization primitives
 - [parking_lot](https://crates.io/crates/parking_lot) - Alternative synchronization primitives
 */
+pub mod affinity;
+pub mod dispatch;
+pub mod identity;
+pub mod lazy_sync_cell;
+mod lock;
+#[cfg(feature = "deadlock-detection")]
+pub mod lock_order;
+pub mod main_thread_cell;
+pub mod once_sync_cell;
+pub mod pending_drops;
+pub mod reentrant_sync_cell;
+pub mod rw_sync_cell;
 pub mod send_cell;
+pub mod sticky_cell;
 pub mod sync_cell;
 pub mod sys;
+pub mod thread_bound_sync_cell;
 pub mod unsafe_send_cell;
 pub mod unsafe_sync_cell;
-
-pub use send_cell::{SendCell, SendFuture};
-pub use sync_cell::SyncCell;
+pub mod violation;
+
+pub use affinity::{AffinityDomain, ExecutorCell, ExecutorFuture};
+pub use identity::{OsThread, ThreadIdentity, ThreadToken};
+pub use lazy_sync_cell::LazySyncCell;
+pub use main_thread_cell::{MainThreadCell, MainThreadMarker};
+pub use once_sync_cell::OnceSyncCell;
+pub use pending_drops::drain_pending_drops;
+pub use reentrant_sync_cell::ReentrantSyncCell;
+pub use rw_sync_cell::RwSyncCell;
+pub use send_cell::{SendCell, SendCellBuilder, SendFuture, WrongThreadError};
+pub use sticky_cell::StickyCell;
+pub use sync_cell::{SyncCell, TryWithError};
+pub use thread_bound_sync_cell::ThreadBoundSyncCell;
 pub use unsafe_send_cell::{UnsafeSendCell, UnsafeSendFuture};
+pub use violation::{
+    Operation, ViolationPolicy, ViolationReport, clear_violation_hook, set_violation_hook,
+    set_violation_policy, violation_policy,
+};
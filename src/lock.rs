@@ -0,0 +1,448 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+/*!
+The lock behind [`crate::SyncCell`].
+
+`std::sync::Mutex` can only block indefinitely or fail immediately, so the cell uses this
+lock instead. It is a flag protected by a short-lived `Mutex`, plus a `Condvar` to park
+waiters, which makes timed acquisition possible. The inner mutex is only held for a few
+instructions and never while user code runs.
+
+Async callers wait in a FIFO queue of wakers instead of blocking. When the lock is released,
+only the waiter at the front of the queue is woken, and a newly arriving async caller queues
+behind existing waiters rather than overtaking them. Blocking callers do not take part in
+the queue: they may be parked on the very thread that would run the front waiter's executor,
+so making them wait for it could deadlock.
+
+The lock records the thread that holds it. Blocking on a lock already held by the current
+thread would deadlock, so every acquisition path reports [`LockError::Reentrant`] instead.
+[`RawLock::lock_reentrant`] goes one step further and lets the holding thread acquire the
+lock again, for nested shared access.
+
+With the `deadlock-detection` feature, blocking acquisitions are also checked against a
+global lock order; see [`crate::lock_order`].
+
+A guard can also wait for a notification, like a `Condvar` paired with a `MutexGuard`: it
+releases the lock, sleeps until [`RawLock::notify`] is called, and reacquires the lock. A
+notification counter, rather than the condvar alone, decides whether a waiter was notified,
+so a notification that arrives between releasing the lock and going to sleep is not lost.
+
+Poisoning follows `std`: if a guard is dropped while its thread is panicking (and was not
+already panicking when the lock was taken), the lock is marked poisoned. Unlike `std`, the
+flag can be cleared, and poisoning can be turned off for a lock entirely.
+*/
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::num::NonZeroU64;
+use std::pin::Pin;
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+pub(crate) struct RawLock {
+    state: Mutex<State>,
+    unlocked: Condvar,
+    /// Signalled by [`RawLock::notify`], for guards in [`LockGuard::wait`].
+    notified: Condvar,
+    poisoning: bool,
+    #[cfg(all(feature = "deadlock-detection", debug_assertions))]
+    order: crate::lock_order::LockId,
+}
+
+struct State {
+    /// The thread holding the lock, if it is locked, as a
+    /// [`crate::sys::current_thread_token`].
+    owner: Option<NonZeroU64>,
+    /// Whether the owner acquired the lock through [`RawLock::lock_reentrant`], for shared
+    /// access only.
+    shared: bool,
+    /// Extra acquisitions through [`RawLock::lock_reentrant`] by the owner.
+    depth: usize,
+    poisoned: bool,
+    /// Async waiters, in arrival order.
+    waiters: VecDeque<Waiter>,
+    next_waiter: u64,
+    /// Incremented by every [`RawLock::notify`].
+    notifications: u64,
+}
+
+struct Waiter {
+    id: u64,
+    waker: Waker,
+}
+
+/// Why the lock could not be acquired.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LockError {
+    /// The lock is held by another thread, and the caller does not block.
+    WouldBlock,
+    /// The lock was not released before the timeout elapsed.
+    TimedOut,
+    /// The current thread already holds the lock, so waiting for it would deadlock.
+    Reentrant,
+}
+
+fn current_thread() -> NonZeroU64 {
+    crate::sys::current_thread_token()
+}
+
+impl State {
+    fn locked(&self) -> bool {
+        self.owner.is_some()
+    }
+
+    fn check_reentrant(&self, me: NonZeroU64) -> Result<(), LockError> {
+        if self.owner == Some(me) {
+            Err(LockError::Reentrant)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the waker of the front waiter, if it should be woken because the lock is free.
+    fn waker_to_wake(&self) -> Option<Waker> {
+        if self.locked() {
+            None
+        } else {
+            self.waiters.front().map(|waiter| waiter.waker.clone())
+        }
+    }
+}
+
+impl RawLock {
+    /// Creates an unlocked lock; `poisoning` controls whether a panic poisons it.
+    ///
+    /// The caller's location identifies the lock in lock-order reports.
+    #[track_caller]
+    pub(crate) const fn new(poisoning: bool) -> RawLock {
+        RawLock {
+            state: Mutex::new(State {
+                owner: None,
+                shared: false,
+                depth: 0,
+                poisoned: false,
+                waiters: VecDeque::new(),
+                next_waiter: 0,
+                notifications: 0,
+            }),
+            unlocked: Condvar::new(),
+            notified: Condvar::new(),
+            poisoning,
+            #[cfg(all(feature = "deadlock-detection", debug_assertions))]
+            order: crate::lock_order::LockId::new(),
+        }
+    }
+
+    pub(crate) fn is_poisoned(&self) -> bool {
+        self.state().poisoned
+    }
+
+    pub(crate) fn clear_poison(&self) {
+        self.state().poisoned = false;
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        //never held while user code runs, so poisoning carries no information
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Checks the lock order before blocking. Must not be called with the state locked,
+    /// since a lock-order hook may run.
+    fn check_order(&self) {
+        #[cfg(all(feature = "deadlock-detection", debug_assertions))]
+        crate::lock_order::check(&self.order);
+    }
+
+    fn acquire(&self, state: MutexGuard<'_, State>, me: NonZeroU64) -> LockGuard<'_> {
+        self.acquire_as(state, me, false)
+    }
+
+    fn acquire_as(
+        &self,
+        mut state: MutexGuard<'_, State>,
+        me: NonZeroU64,
+        shared: bool,
+    ) -> LockGuard<'_> {
+        debug_assert!(!state.locked());
+        state.owner = Some(me);
+        state.shared = shared;
+        let poisoned = state.poisoned;
+        #[cfg(all(feature = "deadlock-detection", debug_assertions))]
+        crate::lock_order::acquired(&self.order);
+        LockGuard {
+            lock: self,
+            poisoned,
+            panicking: std::thread::panicking(),
+            nested: false,
+        }
+    }
+
+    /// Blocks until the lock is acquired.
+    pub(crate) fn lock(&self) -> Result<LockGuard<'_>, LockError> {
+        let me = current_thread();
+        self.check_order();
+        let state = self.state();
+        state.check_reentrant(me)?;
+        let state = self
+            .unlocked
+            .wait_while(state, |state| state.locked())
+            .unwrap_or_else(PoisonError::into_inner);
+        Ok(self.acquire(state, me))
+    }
+
+    /// Acquires the lock for shared access. If the current thread already holds it for shared
+    /// access (through this method), acquires it again instead of failing.
+    ///
+    /// The caller must only hand out shared access under the returned guard. If the current
+    /// thread holds the lock through any other method, this fails with
+    /// [`LockError::Reentrant`], since that holder may have handed out exclusive access.
+    pub(crate) fn lock_reentrant(&self) -> Result<LockGuard<'_>, LockError> {
+        let me = current_thread();
+        let mut state = self.state();
+        if state.owner == Some(me) {
+            if !state.shared {
+                return Err(LockError::Reentrant);
+            }
+            state.depth += 1;
+            return Ok(LockGuard {
+                lock: self,
+                poisoned: state.poisoned,
+                panicking: std::thread::panicking(),
+                nested: true,
+            });
+        }
+        drop(state);
+        self.check_order();
+        let state = self.state();
+        let state = self
+            .unlocked
+            .wait_while(state, |state| state.locked())
+            .unwrap_or_else(PoisonError::into_inner);
+        Ok(self.acquire_as(state, me, true))
+    }
+
+    /// Acquires the lock if it is free, without blocking.
+    pub(crate) fn try_lock(&self) -> Result<LockGuard<'_>, LockError> {
+        let me = current_thread();
+        let state = self.state();
+        state.check_reentrant(me)?;
+        if state.locked() {
+            Err(LockError::WouldBlock)
+        } else {
+            Ok(self.acquire(state, me))
+        }
+    }
+
+    /// Blocks until the lock is acquired or `timeout` has elapsed.
+    pub(crate) fn lock_timeout(&self, timeout: Duration) -> Result<LockGuard<'_>, LockError> {
+        //compute the deadline up front, so spurious wakeups don't extend the wait
+        let deadline = Instant::now().checked_add(timeout);
+        let me = current_thread();
+        let mut state = self.state();
+        state.check_reentrant(me)?;
+        while state.locked() {
+            let remaining = match deadline {
+                Some(deadline) => deadline
+                    .checked_duration_since(Instant::now())
+                    .ok_or(LockError::TimedOut)?,
+                //too far in the future to represent; wait in the longest steps we can
+                None => timeout,
+            };
+            state = self
+                .unlocked
+                .wait_timeout(state, remaining)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+        Ok(self.acquire(state, me))
+    }
+
+    /// Returns a future that resolves once the lock is acquired, without blocking.
+    pub(crate) fn lock_async(&self) -> LockFuture<'_> {
+        LockFuture {
+            lock: self,
+            waiter: None,
+        }
+    }
+
+    /// Wakes one guard (or all of them) waiting in [`LockGuard::wait`].
+    pub(crate) fn notify(&self, all: bool) {
+        let mut state = self.state();
+        state.notifications = state.notifications.wrapping_add(1);
+        drop(state);
+        if all {
+            self.notified.notify_all();
+        } else {
+            self.notified.notify_one();
+        }
+    }
+
+    fn unlock(&self, poison: bool) {
+        let waker = {
+            let mut state = self.state();
+            debug_assert_eq!(
+                state.depth, 0,
+                "the outermost guard was released while nested guards were alive"
+            );
+            state.owner = None;
+            state.poisoned |= poison;
+            state.waker_to_wake()
+        };
+        self.unlocked.notify_one();
+        //wake outside the lock, since waking may run arbitrary executor code
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Releases the lock when dropped.
+pub(crate) struct LockGuard<'a> {
+    lock: &'a RawLock,
+    poisoned: bool,
+    panicking: bool,
+    /// Acquired through [`RawLock::lock_reentrant`] while already held.
+    nested: bool,
+}
+
+impl LockGuard<'_> {
+    /// Whether the lock was poisoned when this guard acquired it.
+    pub(crate) fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Releases the lock, waits until [`RawLock::notify`] is called or `deadline` passes,
+    /// then reacquires the lock. Returns `false` if the deadline passed.
+    ///
+    /// `None` waits without a deadline. Reacquiring the lock is not bounded by the deadline.
+    /// Afterwards, [`Self::is_poisoned`] reflects the lock's state at reacquisition.
+    pub(crate) fn wait(&mut self, deadline: Option<Instant>) -> bool {
+        debug_assert!(!self.nested, "nested guards cannot release the lock");
+        let lock = self.lock;
+        let me = current_thread();
+        let mut state = lock.state();
+        let seen = state.notifications;
+        state.owner = None;
+        let waker = state.waker_to_wake();
+        drop(state);
+        lock.unlocked.notify_one();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+
+        let mut state = lock.state();
+        let mut notified = true;
+        while state.notifications == seen {
+            match deadline {
+                None => {
+                    state = lock
+                        .notified
+                        .wait(state)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+                Some(deadline) => {
+                    let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+                        notified = false;
+                        break;
+                    };
+                    state = lock
+                        .notified
+                        .wait_timeout(state, remaining)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+                }
+            }
+        }
+        let mut state = lock
+            .unlocked
+            .wait_while(state, |state| state.locked())
+            .unwrap_or_else(PoisonError::into_inner);
+        state.owner = Some(me);
+        state.shared = false;
+        self.poisoned = state.poisoned;
+        notified
+    }
+}
+
+impl Drop for LockGuard<'_> {
+    fn drop(&mut self) {
+        if self.nested {
+            //the outermost guard is still alive; it unlocks, and poisons if unwinding
+            self.lock.state().depth -= 1;
+            return;
+        }
+        let poison = self.lock.poisoning && !self.panicking && std::thread::panicking();
+        #[cfg(all(feature = "deadlock-detection", debug_assertions))]
+        crate::lock_order::released(&self.lock.order);
+        self.lock.unlock(poison);
+    }
+}
+
+/// Resolves to a [`LockGuard`] once the lock is acquired.  See [`RawLock::lock_async`].
+pub(crate) struct LockFuture<'a> {
+    lock: &'a RawLock,
+    /// Our id in the wait queue, once queued.
+    waiter: Option<u64>,
+}
+
+impl<'a> Future for LockFuture<'a> {
+    type Output = Result<LockGuard<'a>, LockError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let lock = self.lock;
+        let me = current_thread();
+        let mut state = lock.state();
+        //the holder's closure is running on this thread, so it can't release the lock
+        //until this poll returns
+        if let Err(e) = state.check_reentrant(me) {
+            return Poll::Ready(Err(e));
+        }
+        let our_turn = match self.waiter {
+            None => state.waiters.is_empty(),
+            Some(id) => state.waiters.front().is_some_and(|waiter| waiter.id == id),
+        };
+        if !state.locked() && our_turn {
+            if self.waiter.take().is_some() {
+                state.waiters.pop_front();
+            }
+            return Poll::Ready(Ok(lock.acquire(state, me)));
+        }
+        match self.waiter {
+            Some(id) => {
+                let waiter = state
+                    .waiters
+                    .iter_mut()
+                    .find(|waiter| waiter.id == id)
+                    .expect("queued waiter missing");
+                waiter.waker.clone_from(cx.waker());
+            }
+            None => {
+                let id = state.next_waiter;
+                state.next_waiter += 1;
+                state.waiters.push_back(Waiter {
+                    id,
+                    waker: cx.waker().clone(),
+                });
+                self.waiter = Some(id);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for LockFuture<'_> {
+    fn drop(&mut self) {
+        let Some(id) = self.waiter else {
+            return;
+        };
+        let waker = {
+            let mut state = self.lock.state();
+            state.waiters.retain(|waiter| waiter.id != id);
+            //if we were woken but gave up, pass the turn on
+            state.waker_to_wake()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
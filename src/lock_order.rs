@@ -0,0 +1,332 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+/*!
+Lock-order checking for [`crate::SyncCell`], enabled by the `deadlock-detection` feature.
+
+Two threads that lock the same pair of cells in opposite orders can deadlock, but only if
+their timing lines up, so such bugs tend to surface as rare hangs in production. This
+module catches them deterministically, the first time the inconsistent order is *used*,
+whether or not the threads actually collide.
+
+While the feature is enabled, each thread keeps a stack of the cells it currently holds,
+and a process-wide graph records every "held A, then locked B" pair. Before a thread blocks
+on a cell, the graph is checked: if the cell it is about to lock was itself previously held
+while locking one of the cells this thread now holds (directly or through a chain of other
+cells), the two orders are inconsistent and a [`LockOrderViolation`] is raised.
+
+By default a violation panics, with the creation sites of both cells in the message. A hook
+installed with [`set_lock_order_hook`] receives the report instead, and the lock is then
+acquired as usual.
+
+Tracking only happens in builds with `debug_assertions`; in release builds the feature has
+no cost, and the hook is never called.
+
+Only blocking acquisitions are checked: [`crate::SyncCell::try_with`] and
+[`crate::SyncCell::with_timeout`] cannot wait forever, so they never raise a violation,
+although the cells they hold still count as held for other acquisitions.
+[`crate::sync_cell::with_all`] always locks in the same order, so it is consistent with
+itself.
+
+# Examples
+
+```rust,no_run
+use send_cells::SyncCell;
+
+let accounts = SyncCell::new(vec![100]);
+let audit_log = SyncCell::new(Vec::<String>::new());
+
+// Establishes the order: accounts, then audit_log
+accounts.with(|a| audit_log.with_mut(|log| log.push(format!("balance {}", a[0]))));
+
+// Locking in the opposite order panics in debug builds, even with no other thread involved
+audit_log.with(|_| accounts.with(|_| ()));
+```
+*/
+
+use crate::sys::thread::{self, Thread};
+use std::fmt::{Display, Formatter};
+use std::panic::Location;
+use std::sync::RwLock;
+
+/// A pair of cells that were locked in inconsistent orders.
+///
+/// Passed to the hook installed with [`set_lock_order_hook`], and used as the panic
+/// message when no hook is installed.
+#[derive(Debug, Clone)]
+pub struct LockOrderViolation {
+    held_created_at: &'static Location<'static>,
+    acquired_created_at: &'static Location<'static>,
+    thread: Thread,
+}
+
+impl LockOrderViolation {
+    /// Returns the creation site of the cell the thread was holding.
+    pub fn held_created_at(&self) -> &'static Location<'static> {
+        self.held_created_at
+    }
+
+    /// Returns the creation site of the cell the thread was about to lock.
+    pub fn acquired_created_at(&self) -> &'static Location<'static> {
+        self.acquired_created_at
+    }
+
+    /// Returns the id of the thread that locked the cells in the new order.
+    pub fn thread(&self) -> thread::ThreadId {
+        self.thread.id()
+    }
+
+    /// Returns the name of the thread that locked the cells in the new order, if it has one.
+    pub fn thread_name(&self) -> Option<&str> {
+        self.thread.name()
+    }
+}
+
+impl Display for LockOrderViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "SyncCell lock order inversion on thread {:?}: locking the cell created at {} \
+             while holding the cell created at {}, but they were previously locked in the \
+             opposite order, which can deadlock",
+            self.thread.name().unwrap_or("<unnamed>"),
+            self.acquired_created_at,
+            self.held_created_at
+        )
+    }
+}
+
+static HOOK: RwLock<Option<fn(&LockOrderViolation)>> = RwLock::new(None);
+
+/// Installs a process-wide hook that receives lock-order violations instead of panicking.
+///
+/// It replaces any previously installed hook.
+///
+/// # Examples
+///
+/// ```rust
+/// use send_cells::lock_order::{LockOrderViolation, set_lock_order_hook};
+///
+/// fn log_violation(violation: &LockOrderViolation) {
+///     eprintln!(
+///         "lock order inversion between {} and {}",
+///         violation.held_created_at(),
+///         violation.acquired_created_at()
+///     );
+/// }
+/// set_lock_order_hook(log_violation);
+/// # send_cells::lock_order::clear_lock_order_hook();
+/// ```
+pub fn set_lock_order_hook(hook: fn(&LockOrderViolation)) {
+    *HOOK.write().unwrap_or_else(|e| e.into_inner()) = Some(hook);
+}
+
+/// Removes the hook installed with [`set_lock_order_hook`], so violations panic again.
+pub fn clear_lock_order_hook() {
+    *HOOK.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+#[cfg(debug_assertions)]
+pub(crate) use tracking::{LockId, acquired, check, released};
+
+#[cfg(debug_assertions)]
+mod tracking {
+    use super::{HOOK, LockOrderViolation};
+    use std::cell::RefCell;
+    use std::collections::{HashMap, HashSet};
+    use std::panic::Location;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Mutex, PoisonError};
+
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+    /// "Held the key, then locked each of the values", for every pair seen so far.
+    static GRAPH: Mutex<Option<HashMap<u64, HashSet<u64>>>> = Mutex::new(None);
+
+    thread_local! {
+        /// Cells held by the current thread, in acquisition order.
+        static HELD: RefCell<Vec<(u64, &'static Location<'static>)>> =
+            const { RefCell::new(Vec::new()) };
+    }
+
+    /// Identifies one lock in the order graph.
+    pub(crate) struct LockId {
+        /// Assigned on first use, so that locks can be created in `const` contexts.
+        id: AtomicU64,
+        created_at: &'static Location<'static>,
+    }
+
+    impl LockId {
+        #[track_caller]
+        pub(crate) const fn new() -> LockId {
+            LockId {
+                id: AtomicU64::new(0),
+                created_at: Location::caller(),
+            }
+        }
+
+        fn get(&self) -> u64 {
+            let id = self.id.load(Ordering::Relaxed);
+            if id != 0 {
+                return id;
+            }
+            let fresh = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+            match self
+                .id
+                .compare_exchange(0, fresh, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => fresh,
+                Err(existing) => existing,
+            }
+        }
+    }
+
+    impl Drop for LockId {
+        fn drop(&mut self) {
+            let id = *self.id.get_mut();
+            if id == 0 {
+                return;
+            }
+            let mut graph = GRAPH.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(graph) = graph.as_mut() {
+                graph.remove(&id);
+                for successors in graph.values_mut() {
+                    successors.remove(&id);
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if `to` can be reached from `from` in the graph.
+    fn reachable(graph: &HashMap<u64, HashSet<u64>>, from: u64, to: u64) -> bool {
+        let mut visited = HashSet::new();
+        let mut stack = vec![from];
+        while let Some(node) = stack.pop() {
+            if node == to {
+                return true;
+            }
+            if visited.insert(node) {
+                stack.extend(graph.get(&node).into_iter().flatten());
+            }
+        }
+        false
+    }
+
+    /// Records that the current thread is about to block on `lock`, and reports a violation
+    /// if that contradicts an order seen before.
+    pub(crate) fn check(lock: &LockId) {
+        let id = lock.get();
+        let held = HELD
+            .try_with(|held| held.borrow().clone())
+            .unwrap_or_default();
+        let mut violation = None;
+        {
+            let mut graph = GRAPH.lock().unwrap_or_else(PoisonError::into_inner);
+            let graph = graph.get_or_insert_with(HashMap::new);
+            for &(held_id, held_created_at) in &held {
+                if held_id == id {
+                    //reentry is reported by the lock itself
+                    continue;
+                }
+                if reachable(graph, id, held_id) {
+                    violation.get_or_insert(LockOrderViolation {
+                        held_created_at,
+                        acquired_created_at: lock.created_at,
+                        thread: crate::sys::thread::current(),
+                    });
+                } else {
+                    graph.entry(held_id).or_default().insert(id);
+                }
+            }
+        }
+        //report outside the graph lock, since the hook may lock other cells
+        if let Some(violation) = violation {
+            let hook = *HOOK.read().unwrap_or_else(|e| e.into_inner());
+            match hook {
+                Some(hook) => hook(&violation),
+                None => panic!("{violation}"),
+            }
+        }
+    }
+
+    /// Pushes `lock` onto the current thread's stack of held locks.
+    pub(crate) fn acquired(lock: &LockId) {
+        let entry = (lock.get(), lock.created_at);
+        let _ = HELD.try_with(|held| held.borrow_mut().push(entry));
+    }
+
+    /// Removes `lock` from the current thread's stack of held locks.
+    pub(crate) fn released(lock: &LockId) {
+        let id = lock.get();
+        let _ = HELD.try_with(|held| {
+            let mut held = held.borrow_mut();
+            //guards are not always dropped in reverse order, e.g. by `with_all`
+            if let Some(index) = held.iter().rposition(|&(held_id, _)| held_id == id) {
+                held.remove(index);
+            }
+        });
+    }
+}
+
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use super::*;
+    use crate::SyncCell;
+    use std::sync::Mutex;
+
+    /// The hook is process-wide, so tests that raise violations must not overlap.
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    fn violates(f: impl FnOnce()) -> bool {
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).is_err()
+    }
+
+    #[test]
+    //note: unwind tests are not supported in wasm
+    fn test_inversion_panics() {
+        let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        //the panic unwinds through the outer closure, so avoid poisoning
+        let a = SyncCell::new_without_poisoning(0);
+        let b = SyncCell::new_without_poisoning(0);
+        let c = SyncCell::new_without_poisoning(0);
+        a.with(|_| b.with(|_| ()));
+        //the same order again is fine
+        assert!(!violates(|| a.with(|_| b.with(|_| ()))));
+        assert!(violates(|| b.with(|_| a.with(|_| ()))));
+
+        //an indirect cycle: a -> b -> c, then c -> a
+        b.with(|_| c.with(|_| ()));
+        assert!(violates(|| c.with(|_| a.with(|_| ()))));
+    }
+
+    #[test]
+    fn test_violation_reported_to_hook() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        static REPORTS: Mutex<Vec<LockOrderViolation>> = Mutex::new(Vec::new());
+        fn hook(violation: &LockOrderViolation) {
+            REPORTS.lock().unwrap().push(violation.clone());
+        }
+
+        let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        let first_line = line!() + 1;
+        let first = SyncCell::new(1);
+        let second = SyncCell::new(2);
+        set_lock_order_hook(hook);
+        //the orders conflict even though the threads never overlap
+        std::thread::scope(|scope| {
+            scope.spawn(|| first.with(|_| second.with(|_| ())));
+        });
+        let sum = std::thread::scope(|scope| {
+            scope
+                .spawn(|| second.with(|a| first.with(|b| a + b)))
+                .join()
+                .unwrap()
+        });
+        clear_lock_order_hook();
+        //with a hook installed, the lock is still acquired
+        assert_eq!(sum, 3);
+
+        let reports = REPORTS.lock().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].acquired_created_at().line(), first_line);
+        assert_eq!(reports[0].held_created_at().line(), first_line + 1);
+    }
+}
@@ -0,0 +1,195 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+/*!
+A `Send + Sync` cell for values that belong to the main thread.
+
+UI toolkits and many FFI libraries require some objects to be used only on the main thread.
+A [`crate::SendCell`] binds to whatever thread happens to create it, and checks it on every
+access. This module instead provides:
+
+- [`MainThreadMarker`], a zero-sized proof that the current thread is the main thread (as
+  decided by [`crate::sys::is_main_thread`]). It is `!Send`, so it cannot leave that thread.
+- [`MainThreadCell<T>`], which is `Send + Sync` for any `T`, and whose value can only be
+  reached by presenting a marker. Since the marker is the proof, access has no runtime check.
+
+Values are created on the main thread too, so a `MainThreadCell` never moves its value
+between threads. Dropping the cell on another thread is handled as for a `SendCell`: it is
+a violation, unless the cell was created with [`MainThreadCell::new_deferring_drop`].
+
+# Examples
+
+```rust
+use send_cells::sys::mark_main_thread;
+use send_cells::{MainThreadCell, MainThreadMarker};
+use std::rc::Rc;
+use std::sync::Arc;
+
+// Early in main
+mark_main_thread();
+
+let marker = MainThreadMarker::new().unwrap();
+let window = Arc::new(MainThreadCell::new_deferring_drop(marker, Rc::new("window")));
+
+let shared = window.clone();
+std::thread::spawn(move || {
+    // Other threads can hold the cell, but cannot get a marker to open it
+    assert!(MainThreadMarker::new().is_none());
+    drop(shared);
+}).join().unwrap();
+
+assert_eq!(**window.get(marker), "window");
+```
+*/
+
+use crate::send_cell::SendCell;
+use std::fmt::{Debug, Formatter};
+use std::marker::PhantomData;
+
+/// Proof that the current thread is the main thread.
+///
+/// See the [module documentation](crate::main_thread_cell) for details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MainThreadMarker {
+    _not_send: PhantomData<*const ()>,
+}
+
+impl MainThreadMarker {
+    /// Returns a marker if the current thread is the main thread.
+    ///
+    /// See [`crate::sys::is_main_thread`] for how the main thread is recognized.
+    #[inline]
+    pub fn new() -> Option<MainThreadMarker> {
+        if crate::sys::is_main_thread() {
+            //safe because we just checked
+            Some(unsafe { MainThreadMarker::new_unchecked() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns a marker without checking the current thread.
+    ///
+    /// # Safety
+    ///
+    /// The current thread must be the main thread, as decided by
+    /// [`crate::sys::is_main_thread`].
+    #[inline]
+    pub const unsafe fn new_unchecked() -> MainThreadMarker {
+        MainThreadMarker {
+            _not_send: PhantomData,
+        }
+    }
+}
+
+/// A `Send + Sync` cell whose value can only be accessed on the main thread.
+///
+/// See the [module documentation](crate::main_thread_cell) for details.
+pub struct MainThreadCell<T> {
+    //always bound to the main thread
+    inner: SendCell<T>,
+}
+
+// SAFETY: the value is only reachable with a MainThreadMarker, which only exists on the
+// main thread, so it is never reached from two threads.
+unsafe impl<T> Sync for MainThreadCell<T> {}
+
+impl<T> MainThreadCell<T> {
+    /// Creates a new cell on the main thread.
+    #[inline]
+    #[track_caller]
+    pub fn new(marker: MainThreadMarker, value: T) -> MainThreadCell<T> {
+        let _ = marker;
+        MainThreadCell {
+            inner: SendCell::new(value),
+        }
+    }
+
+    /// Creates a new cell on the main thread, deferring a drop on another thread to the main
+    /// thread.
+    ///
+    /// See [`SendCell::new_deferring_drop`].
+    #[inline]
+    #[track_caller]
+    pub fn new_deferring_drop(marker: MainThreadMarker, value: T) -> MainThreadCell<T>
+    where
+        T: 'static,
+    {
+        let _ = marker;
+        MainThreadCell {
+            inner: SendCell::new_deferring_drop(value),
+        }
+    }
+
+    /// Accesses the value.
+    #[inline]
+    pub fn get(&self, marker: MainThreadMarker) -> &T {
+        let _ = marker;
+        //safe because the cell was created on the main thread, and the marker proves we are on it
+        unsafe { self.inner.get_unchecked() }
+    }
+
+    /// Accesses the value mutably.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::{MainThreadCell, MainThreadMarker};
+    ///
+    /// send_cells::sys::mark_main_thread();
+    /// let marker = MainThreadMarker::new().unwrap();
+    /// let mut cell = MainThreadCell::new(marker, vec![1]);
+    /// cell.get_mut(marker).push(2);
+    /// assert_eq!(cell.into_inner(marker), vec![1, 2]);
+    /// ```
+    #[inline]
+    pub fn get_mut(&mut self, marker: MainThreadMarker) -> &mut T {
+        let _ = marker;
+        unsafe { self.inner.get_unchecked_mut() }
+    }
+
+    /// Consumes the cell and returns the value.
+    #[inline]
+    pub fn into_inner(self, marker: MainThreadMarker) -> T {
+        let _ = marker;
+        unsafe { self.inner.into_unchecked_inner() }
+    }
+}
+
+impl<T> Debug for MainThreadCell<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        //the value is only reachable with a marker
+        f.debug_struct("MainThreadCell").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_main_thread_cell() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        //note: unwind tests are not supported in wasm
+        //the main thread is process-wide, so this is the only test that marks one
+        crate::sys::mark_main_thread();
+        crate::sys::mark_main_thread();
+        let marker = MainThreadMarker::new().unwrap();
+        let cell = Arc::new(MainThreadCell::new(marker, Rc::new(1)));
+
+        let shared = cell.clone();
+        std::thread::spawn(move || {
+            assert!(!crate::sys::is_main_thread());
+            assert!(MainThreadMarker::new().is_none());
+            assert!(std::panic::catch_unwind(crate::sys::mark_main_thread).is_err());
+            assert_eq!(format!("{shared:?}"), "MainThreadCell { .. }");
+        })
+        .join()
+        .unwrap();
+
+        let mut cell = Arc::into_inner(cell).unwrap();
+        *cell.get_mut(marker) = Rc::new(2);
+        assert_eq!(**cell.get(marker), 2);
+        assert_eq!(*cell.into_inner(marker), 2);
+    }
+}
@@ -0,0 +1,212 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+/*!
+A [`crate::SyncCell`] that starts out empty and is initialized at most once.
+
+This module provides [`OnceSyncCell<T>`], the `SyncCell` counterpart of
+[`std::sync::OnceLock`]. `OnceLock` hands out `&T` to every thread and therefore requires
+`T: Sync`; `OnceSyncCell` only hands the value to closures running under its lock, so it
+only requires `T: Send`, like `SyncCell`.
+
+Initialization runs under the same lock as every other access, so exactly one thread runs
+the initializer and the others wait for it. Since [`OnceSyncCell::new`] is a `const fn`, the
+cell can be placed in a `static`.
+
+# Panics
+
+As with [`crate::SyncCell`], a panic inside a closure (including the initializer) poisons
+the cell, and accessing the cell from inside one of its own closures panics instead of
+deadlocking.
+
+# Examples
+
+```rust
+use send_cells::OnceSyncCell;
+use std::cell::RefCell;
+
+// A global non-Sync object, created on first use
+static CONTEXT: OnceSyncCell<RefCell<String>> = OnceSyncCell::new();
+
+let len = CONTEXT.with_or_init(|| RefCell::new(String::from("ctx")), |ctx| ctx.borrow().len());
+assert_eq!(len, 3);
+assert!(CONTEXT.is_initialized());
+```
+*/
+
+use crate::sync_cell::SyncCell;
+use std::fmt::{Debug, Formatter};
+
+/// A thread-safe cell for a non-Sync value that is written at most once.
+///
+/// See the [module documentation](crate::once_sync_cell) for details.
+pub struct OnceSyncCell<T> {
+    inner: SyncCell<Option<T>>,
+}
+
+impl<T> OnceSyncCell<T> {
+    /// Creates a new, uninitialized cell.
+    #[inline]
+    #[track_caller]
+    pub const fn new() -> OnceSyncCell<T> {
+        OnceSyncCell {
+            inner: SyncCell::new(None),
+        }
+    }
+
+    /// Returns `true` if the cell has been initialized.
+    #[inline]
+    pub fn is_initialized(&self) -> bool {
+        self.inner.with(Option::is_some)
+    }
+
+    /// Initializes the cell with `value`, or returns it if the cell was already initialized.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::OnceSyncCell;
+    ///
+    /// let cell = OnceSyncCell::new();
+    /// assert_eq!(cell.set(1), Ok(()));
+    /// assert_eq!(cell.set(2), Err(2));
+    /// assert_eq!(cell.with(|v| *v), Some(1));
+    /// ```
+    #[inline]
+    pub fn set(&self, value: T) -> Result<(), T> {
+        self.inner.with_mut(|slot| match slot {
+            Some(_) => Err(value),
+            None => {
+                *slot = Some(value);
+                Ok(())
+            }
+        })
+    }
+
+    /// Calls `f` with the value, or returns `None` if the cell is not initialized.
+    #[inline]
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.inner.with(|slot| slot.as_ref().map(f))
+    }
+
+    /// Calls `f` with the value mutably, or returns `None` if the cell is not initialized.
+    #[inline]
+    pub fn with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        self.inner.with_mut(|slot| slot.as_mut().map(f))
+    }
+
+    /// Calls `f` with the value, initializing it with `init` first if needed.
+    ///
+    /// If several threads call this at once, only one runs `init`; the others wait for it
+    /// and then see its value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cell is poisoned, or if `init` or `f` accesses this cell.
+    #[inline]
+    pub fn with_or_init<R>(&self, init: impl FnOnce() -> T, f: impl FnOnce(&T) -> R) -> R {
+        self.inner.with_mut(|slot| f(slot.get_or_insert_with(init)))
+    }
+
+    /// Calls `f` with the value mutably, initializing it with `init` first if needed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cell is poisoned, or if `init` or `f` accesses this cell.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::OnceSyncCell;
+    /// use std::cell::RefCell;
+    ///
+    /// let log = OnceSyncCell::new();
+    /// log.with_mut_or_init(|| RefCell::new(Vec::new()), |log| log.get_mut().push("first"));
+    /// log.with_mut_or_init(|| unreachable!(), |log| log.get_mut().push("second"));
+    /// assert_eq!(log.into_inner().unwrap().into_inner(), vec!["first", "second"]);
+    /// ```
+    #[inline]
+    pub fn with_mut_or_init<R>(&self, init: impl FnOnce() -> T, f: impl FnOnce(&mut T) -> R) -> R {
+        self.inner.with_mut(|slot| f(slot.get_or_insert_with(init)))
+    }
+
+    /// Takes the value out of the cell, leaving it uninitialized.
+    #[inline]
+    pub fn take(&mut self) -> Option<T> {
+        self.inner.get_mut().take()
+    }
+
+    /// Consumes the cell and returns the value, if it was initialized.
+    #[inline]
+    pub fn into_inner(self) -> Option<T> {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: Debug> Debug for OnceSyncCell<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut d = f.debug_tuple("OnceSyncCell");
+        match self.with(|value| d.field(value).finish()) {
+            Some(result) => result,
+            None => d.field(&format_args!("<uninit>")).finish(),
+        }
+    }
+}
+
+impl<T> Default for OnceSyncCell<T> {
+    #[track_caller]
+    fn default() -> OnceSyncCell<T> {
+        OnceSyncCell::new()
+    }
+}
+
+impl<T> From<T> for OnceSyncCell<T> {
+    #[track_caller]
+    fn from(value: T) -> Self {
+        OnceSyncCell {
+            inner: SyncCell::new(Some(value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_initialized_once() {
+        let mut cell = OnceSyncCell::new();
+        assert_eq!(cell.with(|v: &Rc<i32>| **v), None);
+        assert_eq!(cell.with_or_init(|| Rc::new(1), |v| **v), 1);
+        assert_eq!(cell.with_or_init(|| Rc::new(2), |v| **v), 1);
+        assert_eq!(cell.set(Rc::new(3)), Err(Rc::new(3)));
+        assert_eq!(format!("{cell:?}"), "OnceSyncCell(1)");
+        assert_eq!(cell.take(), Some(Rc::new(1)));
+        assert!(!cell.is_initialized());
+        assert_eq!(format!("{cell:?}"), "OnceSyncCell(<uninit>)");
+    }
+
+    #[test]
+    fn test_concurrent_init_runs_once() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static CELL: OnceSyncCell<std::cell::Cell<usize>> = OnceSyncCell::new();
+        static INITS: AtomicUsize = AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    CELL.with_or_init(
+                        || {
+                            INITS.fetch_add(1, Ordering::SeqCst);
+                            std::cell::Cell::new(0)
+                        },
+                        |count| count.set(count.get() + 1),
+                    )
+                });
+            }
+        });
+        assert_eq!(INITS.load(Ordering::SeqCst), 1);
+        assert_eq!(CELL.with(|count| count.get()), Some(4));
+    }
+}
@@ -0,0 +1,181 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+/*!
+Deferred cleanup of values that were dropped on the wrong thread.
+
+A [`crate::SendCell`] created with [`crate::SendCell::new_deferring_drop`] does not panic
+when it is dropped on a thread other than its origin. Instead, the wrapped value is moved
+into a "pending drops" queue owned by the origin thread. The queue is drained on that thread:
+
+- the next time the thread touches any `SendCell` (construction, access, or drop)
+- when the thread calls [`drain_pending_drops`] explicitly
+- when the thread exits
+
+Values deferred after their origin thread has exited can never be dropped safely,
+so they are leaked.
+
+# Examples
+
+```rust
+use send_cells::SendCell;
+use std::rc::Rc;
+
+let cell = SendCell::new_deferring_drop(Rc::new(42));
+
+// Dropping on another thread does not panic; the value is queued instead
+std::thread::spawn(move || drop(cell)).join().unwrap();
+
+// Run the deferred drop on the origin thread
+assert_eq!(send_cells::drain_pending_drops(), 1);
+```
+*/
+
+use crate::unsafe_send_cell::UnsafeSendCell;
+use std::any::Any;
+use std::cell::OnceCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The number of values queued on all threads.
+///
+/// Checked before the thread-local queue, so that accessing a `SendCell` costs a single
+/// atomic load while nothing is pending anywhere.
+static ANY_PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// A queue of values waiting to be dropped on their origin thread.
+pub(crate) struct PendingDrops {
+    state: Mutex<State>,
+    len: AtomicUsize,
+}
+
+struct State {
+    values: Vec<UnsafeSendCell<Box<dyn Any>>>,
+    /// Set once the origin thread has exited; later values are leaked.
+    closed: bool,
+}
+
+impl PendingDrops {
+    /// Queues a value to be dropped on the origin thread.
+    ///
+    /// The `'static` bound is required because the value may outlive the cell that held it.
+    pub(crate) fn push<T: 'static>(&self, value: UnsafeSendCell<T>) {
+        //safe because the value is only unwrapped (and dropped) on the origin thread
+        let value = unsafe { value.into_inner() };
+        let boxed: Box<dyn Any> = Box::new(value);
+        let boxed = unsafe { UnsafeSendCell::new_unchecked(boxed) };
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            //the origin thread is gone, so there is no thread on which this can be dropped
+            std::mem::forget(boxed);
+        } else {
+            state.values.push(boxed);
+            self.len.fetch_add(1, Ordering::Release);
+            ANY_PENDING.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    /// Drops all queued values.  Must only be called on the origin thread.
+    fn drain(&self, close: bool) -> usize {
+        let values = {
+            let mut state = self.state.lock().unwrap();
+            state.closed |= close;
+            self.len.store(0, Ordering::Release);
+            ANY_PENDING.fetch_sub(state.values.len(), Ordering::Relaxed);
+            std::mem::take(&mut state.values)
+        };
+        //drop outside the lock, since a value's Drop may itself touch a SendCell
+        let count = values.len();
+        for value in values {
+            //safe because we are on the origin thread
+            drop(unsafe { value.into_inner() });
+        }
+        count
+    }
+}
+
+impl Drop for PendingDrops {
+    fn drop(&mut self) {
+        //The last reference may be released on any thread, so whatever is left
+        //cannot be dropped here.
+        let state = self.state.get_mut().unwrap_or_else(|e| e.into_inner());
+        ANY_PENDING.fetch_sub(state.values.len(), Ordering::Relaxed);
+        for value in state.values.drain(..) {
+            std::mem::forget(value);
+        }
+    }
+}
+
+/// The current thread's queue, drained and closed when the thread exits.
+struct LocalQueue(Arc<PendingDrops>);
+
+impl Drop for LocalQueue {
+    fn drop(&mut self) {
+        self.0.drain(true);
+    }
+}
+
+thread_local! {
+    static LOCAL: OnceCell<LocalQueue> = const { OnceCell::new() };
+}
+
+/// Returns the current thread's queue, creating it if necessary.
+pub(crate) fn current_queue() -> Arc<PendingDrops> {
+    LOCAL.with(|local| {
+        local
+            .get_or_init(|| {
+                LocalQueue(Arc::new(PendingDrops {
+                    state: Mutex::new(State {
+                        values: Vec::new(),
+                        closed: false,
+                    }),
+                    len: AtomicUsize::new(0),
+                }))
+            })
+            .0
+            .clone()
+    })
+}
+
+/// Drains the current thread's queue if it has any values waiting.
+///
+/// This is a single atomic load when nothing is pending on any thread, so it can be called
+/// on every `SendCell` access.
+#[inline]
+pub(crate) fn drain_if_pending() {
+    if ANY_PENDING.load(Ordering::Acquire) == 0 {
+        return;
+    }
+    let _ = LOCAL.try_with(|local| {
+        if let Some(queue) = local.get() {
+            if queue.0.len.load(Ordering::Acquire) != 0 {
+                queue.0.drain(false);
+            }
+        }
+    });
+}
+
+/// Drops every value that was deferred to the current thread.
+///
+/// Values end up in this queue when a cell created with
+/// [`crate::SendCell::new_deferring_drop`] on this thread is dropped on another thread.
+/// The queue is also drained automatically whenever this thread touches a `SendCell`, so
+/// calling this function is only necessary to release resources promptly.
+///
+/// Returns the number of values that were dropped.
+///
+/// # Examples
+///
+/// ```rust
+/// use send_cells::SendCell;
+/// use std::rc::Rc;
+///
+/// let cell = SendCell::new_deferring_drop(Rc::new("resource"));
+/// std::thread::spawn(move || drop(cell)).join().unwrap();
+///
+/// assert_eq!(send_cells::drain_pending_drops(), 1);
+/// assert_eq!(send_cells::drain_pending_drops(), 0);
+/// ```
+pub fn drain_pending_drops() -> usize {
+    LOCAL
+        .try_with(|local| local.get().map_or(0, |queue| queue.0.drain(false)))
+        .unwrap_or(0)
+}
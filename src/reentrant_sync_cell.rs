@@ -0,0 +1,213 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+/*!
+A [`crate::SyncCell`] variant that permits nested shared access on the same thread.
+
+[`crate::SyncCell`] panics when a closure accesses the same cell again, because waiting for
+the lock would deadlock. [`ReentrantSyncCell<T>`] relaxes that for shared access: a thread
+that is inside [`ReentrantSyncCell::with`] may call `with` again, and the nested call runs
+immediately. Other threads still wait for the outermost closure to return.
+
+Exclusive access cannot nest, since it would alias the shared references handed out by
+the outer closures. [`ReentrantSyncCell::with_mut`] panics if the current thread is already
+inside any closure on the cell.
+
+# Examples
+
+```rust
+use send_cells::reentrant_sync_cell::ReentrantSyncCell;
+
+struct Tree {
+    children: Vec<usize>,
+}
+
+fn count(cell: &ReentrantSyncCell<Vec<Tree>>, node: usize) -> usize {
+    // Recursion re-enters the cell on the same thread
+    cell.with(|nodes| 1 + nodes[node].children.iter().map(|&c| count(cell, c)).sum::<usize>())
+}
+
+let cell = ReentrantSyncCell::new(vec![
+    Tree { children: vec![1, 2] },
+    Tree { children: vec![] },
+    Tree { children: vec![] },
+]);
+assert_eq!(count(&cell, 0), 3);
+```
+*/
+
+use crate::lock::{LockGuard, RawLock};
+use crate::unsafe_sync_cell::UnsafeSyncCell;
+use std::fmt::{Debug, Formatter};
+
+/// A mutex-protected cell whose shared accessor can be re-entered on the same thread.
+///
+/// See the [module documentation](crate::reentrant_sync_cell) for details.
+///
+/// # Thread Safety
+///
+/// Like `SyncCell`, the cell implements both `Send` and `Sync` when the wrapped type
+/// implements `Send`. Nested shared references only ever exist on one thread at a time.
+pub struct ReentrantSyncCell<T> {
+    inner: UnsafeSyncCell<T>,
+    lock: RawLock,
+}
+
+impl<T> ReentrantSyncCell<T> {
+    /// Creates a new `ReentrantSyncCell` wrapping the given value.
+    #[inline]
+    #[track_caller]
+    pub const fn new(value: T) -> ReentrantSyncCell<T> {
+        ReentrantSyncCell {
+            inner: UnsafeSyncCell::new(value),
+            lock: RawLock::new(true),
+        }
+    }
+
+    /// Accesses the underlying value through a closure.
+    ///
+    /// If the current thread is already inside `with` on this cell, the closure runs
+    /// immediately; otherwise this waits for the lock.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the mutex is poisoned (i.e., another thread panicked while holding the lock),
+    /// or if called from inside [`Self::with_mut`] on this cell on the same thread.
+    #[inline]
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        let _guard = checked(self.lock.lock_reentrant().ok());
+        let value = unsafe { self.inner.get() };
+        f(value)
+    }
+
+    /// Accesses the underlying value mutably through a closure.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the mutex is poisoned (i.e., another thread panicked while holding the lock),
+    /// or if called from inside any other closure on this cell on the same thread.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::reentrant_sync_cell::ReentrantSyncCell;
+    ///
+    /// let cell = ReentrantSyncCell::new(1);
+    /// cell.with_mut(|v| *v += 1);
+    /// assert_eq!(cell.with(|a| cell.with(|b| *a + *b)), 4);
+    /// ```
+    #[inline]
+    pub fn with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let _guard = checked(self.lock.lock().ok());
+        //safe since we hold the lock, and no shared access is nested inside this closure
+        let value = unsafe { self.inner.get_mut_unchecked() };
+        f(value)
+    }
+
+    /// Returns `true` if a closure panicked while holding the lock.
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.lock.is_poisoned()
+    }
+
+    /// Clears the poisoned state, making the cell usable again.
+    #[inline]
+    pub fn clear_poison(&self) {
+        self.lock.clear_poison()
+    }
+
+    /// Consumes the cell and returns the wrapped value.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
+
+/// Panics on a reentrant exclusive access or a poisoned lock.
+#[inline]
+fn checked(guard: Option<LockGuard<'_>>) -> LockGuard<'_> {
+    let Some(guard) = guard else {
+        panic!(
+            "ReentrantSyncCell accessed mutably while this thread is already inside one of its closures"
+        );
+    };
+    if guard.is_poisoned() {
+        panic!("ReentrantSyncCell is poisoned: a thread panicked while holding the lock");
+    }
+    guard
+}
+
+// SAFETY: ReentrantSyncCell<T> can be Send when T: Send, since moving the cell moves the value.
+unsafe impl<T: Send> Send for ReentrantSyncCell<T> {}
+
+// SAFETY: ReentrantSyncCell<T> can be Sync when T: Send because the lock is held by one thread
+// at a time; nested shared references are all on that thread.
+unsafe impl<T: Send> Sync for ReentrantSyncCell<T> {}
+
+impl<T: Debug> Debug for ReentrantSyncCell<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.with(|value| value.fmt(f))
+    }
+}
+
+impl<T: Default> Default for ReentrantSyncCell<T> {
+    #[track_caller]
+    fn default() -> ReentrantSyncCell<T> {
+        ReentrantSyncCell::new(T::default())
+    }
+}
+
+impl<T> From<T> for ReentrantSyncCell<T> {
+    #[track_caller]
+    fn from(value: T) -> Self {
+        ReentrantSyncCell::new(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_nested_shared_access() {
+        let cell = ReentrantSyncCell::new(vec![1, 2, 3]);
+        let total = cell.with(|a| cell.with(|b| cell.with(|c| a.len() + b.len() + c.len())));
+        assert_eq!(total, 9);
+        cell.with_mut(|v| v.push(4));
+        assert_eq!(cell.into_inner(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    //note: unwind tests are not supported in wasm
+    fn test_nested_mutable_access_panics() {
+        let cell = ReentrantSyncCell::new(0);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cell.with(|_| cell.with_mut(|v| *v += 1))
+        }));
+        assert!(result.is_err());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cell.with_mut(|_| cell.with(|v| *v))
+        }));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_other_threads_wait_for_outermost() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        use std::sync::mpsc;
+
+        let cell = ReentrantSyncCell::new(0);
+        let (inside_tx, inside_rx) = mpsc::channel();
+        std::thread::scope(|scope| {
+            cell.with(|_| {
+                scope.spawn(|| {
+                    inside_tx.send(()).unwrap();
+                    cell.with_mut(|v| *v = 1);
+                });
+                inside_rx.recv().unwrap();
+                //the other thread is blocked until we return, even after nesting
+                cell.with(|v| assert_eq!(*v, 0));
+            });
+        });
+        assert_eq!(cell.into_inner(), 1);
+    }
+}
@@ -0,0 +1,464 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+/*!
+A reader-writer variant of [`crate::SyncCell`] that allows concurrent readers.
+
+This module provides [`RwSyncCell<T>`], which has the same closure-based API as
+[`crate::SyncCell`], but is backed by a [`std::sync::RwLock`]: [`RwSyncCell::with`] takes a
+shared lock, so any number of threads can read at once, while [`RwSyncCell::with_mut`]
+takes an exclusive lock.
+
+# Use Cases
+
+- Read-heavy shared state, such as configuration maps or caches
+- Any place a `SyncCell` is used mostly through `with`
+
+# Thread Safety Model
+
+Concurrent readers each receive a `&T` at the same time, on different threads. That is only
+sound if `T: Sync`, so unlike `SyncCell`, which is `Sync` whenever `T: Send`,
+`RwSyncCell<T>` is only `Sync` when `T: Send + Sync`:
+
+| Bound on `T`   | `SyncCell<T>`  | `RwSyncCell<T>` |
+|----------------|----------------|-----------------|
+| `Send`         | `Send + Sync`  | `Send`          |
+| `Send + Sync`  | `Send + Sync`  | `Send + Sync`   |
+
+For `T: Send` types that are not `Sync` (such as `Cell` or `RefCell`), use `SyncCell`.
+
+# Poisoning
+
+Like `SyncCell`, a panic inside a closure passed to either [`RwSyncCell::with`] or
+[`RwSyncCell::with_mut`] poisons the cell, and later accesses panic. A reader can still
+leave a `T` with interior mutability half-modified, so readers poison it too.
+
+# Examples
+
+```rust
+use send_cells::RwSyncCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+
+let config = Arc::new(RwSyncCell::new(HashMap::from([("mode", "fast")])));
+
+let readers: Vec<_> = (0..4)
+    .map(|_| {
+        let config = Arc::clone(&config);
+        thread::spawn(move || config.with(|map| map["mode"]))
+    })
+    .collect();
+for reader in readers {
+    assert_eq!(reader.join().unwrap(), "fast");
+}
+
+config.with_mut(|map| map.insert("mode", "safe"));
+assert_eq!(config.with(|map| map["mode"]), "safe");
+```
+*/
+
+use crate::unsafe_sync_cell::UnsafeSyncCell;
+use std::fmt::{Debug, Formatter};
+use std::hash::{Hash, Hasher};
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A cell that allows concurrent shared access and exclusive mutable access.
+///
+/// See the [module documentation](crate::rw_sync_cell) for the bounds required to share it
+/// between threads.
+///
+/// # Examples
+///
+/// ```rust
+/// use send_cells::RwSyncCell;
+///
+/// let cell = RwSyncCell::new(vec![1, 2, 3]);
+///
+/// let sum = cell.with(|v| v.iter().sum::<i32>());
+/// assert_eq!(sum, 6);
+///
+/// cell.with_mut(|v| v.push(4));
+/// assert_eq!(cell.with(|v| v.len()), 4);
+/// ```
+///
+/// # Thread Safety
+///
+/// The cell implements `Send` when the wrapped type implements `Send`, and `Sync` when it
+/// implements both `Send` and `Sync`.
+pub struct RwSyncCell<T> {
+    inner: UnsafeSyncCell<T>,
+    lock: RwLock<()>,
+    //set when any closure panics, since `lock` is only poisoned by writers
+    poisoned: AtomicBool,
+}
+
+impl<T> RwSyncCell<T> {
+    /// Creates a new `RwSyncCell` wrapping the given value.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::RwSyncCell;
+    ///
+    /// let cell = RwSyncCell::new(42);
+    /// assert_eq!(cell.with(|v| *v), 42);
+    /// ```
+    #[inline]
+    pub fn new(value: T) -> RwSyncCell<T> {
+        RwSyncCell {
+            inner: UnsafeSyncCell::new(value),
+            lock: RwLock::new(()),
+            poisoned: AtomicBool::new(false),
+        }
+    }
+
+    /// Accesses the underlying value through a synchronous closure, under a shared lock.
+    ///
+    /// Readers on other threads may run at the same time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cell is poisoned (i.e., a thread panicked inside [`Self::with`] or
+    /// [`Self::with_mut`]).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::RwSyncCell;
+    /// use std::collections::HashMap;
+    ///
+    /// let cell = RwSyncCell::new(HashMap::from([("key", "value")]));
+    /// assert_eq!(cell.with(|map| map.get("key").copied()), Some("value"));
+    /// ```
+    #[inline]
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        let _guard = self.lock.read().unwrap();
+        let _poison = self.unpoisoned();
+        //safe since we hold a shared lock, so there is no writer
+        let value = unsafe { self.inner.get() };
+        f(value)
+    }
+
+    /// Accesses the underlying value mutably through a synchronous closure, under an
+    /// exclusive lock.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cell is poisoned (i.e., a thread panicked inside [`Self::with`] or
+    /// [`Self::with_mut`]).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::RwSyncCell;
+    ///
+    /// let cell = RwSyncCell::new(Vec::new());
+    /// cell.with_mut(|v| v.push("item"));
+    /// assert_eq!(cell.with(|v| v.len()), 1);
+    /// ```
+    #[inline]
+    pub fn with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let _guard = self.lock.write().unwrap();
+        let _poison = self.unpoisoned();
+        //safe since we hold the exclusive lock
+        let value = unsafe { self.inner.get_mut_unchecked() };
+        f(value)
+    }
+
+    /// Panics if the cell is poisoned, and otherwise returns a guard that poisons it
+    /// if the current thread panics before the guard is dropped.
+    #[inline]
+    fn unpoisoned(&self) -> PoisonOnPanic<'_> {
+        if self.poisoned.load(Ordering::Relaxed) {
+            poisoned();
+        }
+        PoisonOnPanic {
+            poisoned: &self.poisoned,
+            panicking: std::thread::panicking(),
+        }
+    }
+
+    /// Consumes the cell and returns the wrapped value.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::RwSyncCell;
+    ///
+    /// let cell = RwSyncCell::new(String::from("hello"));
+    /// assert_eq!(cell.into_inner(), "hello");
+    /// ```
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+
+    /// Unsafely accesses the underlying value without acquiring the lock.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that:
+    /// - No thread is currently accessing the value mutably
+    /// - The access is properly synchronized through external means
+    pub unsafe fn with_unchecked(&self) -> &T {
+        unsafe {
+            // SAFETY: Caller guarantees proper synchronization
+            self.inner.get()
+        }
+    }
+
+    /// Unsafely accesses the underlying value mutably without acquiring the lock.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that:
+    /// - No other thread is currently accessing the value
+    /// - The access is properly synchronized through external means
+    /// - No other references (mutable or immutable) to the value exist
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn with_mut_unchecked(&self) -> &mut T {
+        unsafe {
+            // SAFETY: Caller guarantees proper synchronization
+            self.inner.get_mut_unchecked()
+        }
+    }
+}
+
+#[cold]
+fn poisoned() -> ! {
+    panic!("RwSyncCell is poisoned: a thread panicked while accessing the value")
+}
+
+/// Poisons an [`RwSyncCell`] when dropped during a panic that started after it was created.
+struct PoisonOnPanic<'a> {
+    poisoned: &'a AtomicBool,
+    panicking: bool,
+}
+
+impl Drop for PoisonOnPanic<'_> {
+    fn drop(&mut self) {
+        if !self.panicking && std::thread::panicking() {
+            self.poisoned.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+// SAFETY: RwSyncCell<T> can be Send when T: Send, since moving the cell moves the value.
+unsafe impl<T: Send> Send for RwSyncCell<T> {}
+
+// SAFETY: RwSyncCell<T> hands out `&T` to several threads at once under the shared lock,
+// which requires T: Sync, and `&mut T` to one thread at a time under the exclusive lock,
+// which requires T: Send.
+unsafe impl<T: Send + Sync> Sync for RwSyncCell<T> {}
+
+// ===========================================================================================
+// BOILERPLATE TRAIT IMPLEMENTATIONS
+// ===========================================================================================
+// As with SyncCell, all trait implementations go through the closure-based access methods,
+// and only ever take the shared lock.
+
+impl<T: Debug> Debug for RwSyncCell<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.with(|value| value.fmt(f))
+    }
+}
+
+impl<T: std::fmt::Display> std::fmt::Display for RwSyncCell<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.with(|value| value.fmt(f))
+    }
+}
+
+impl<T: Default> Default for RwSyncCell<T> {
+    fn default() -> RwSyncCell<T> {
+        RwSyncCell::new(T::default())
+    }
+}
+
+impl<T> From<T> for RwSyncCell<T> {
+    fn from(value: T) -> Self {
+        RwSyncCell::new(value)
+    }
+}
+
+// Clone creates a new independent RwSyncCell with a cloned value
+impl<T: Clone> Clone for RwSyncCell<T> {
+    fn clone(&self) -> Self {
+        self.with(|value| RwSyncCell::new(value.clone()))
+    }
+}
+
+/// Calls `f` with the values of two cells, read-locked in address order.
+///
+/// Nesting `with` calls instead would make the lock order depend on the argument order, and
+/// would read-lock a cell twice when comparing it with itself. Either can deadlock, since a
+/// waiting writer blocks new readers.
+fn with_pair<T, R>(a: &RwSyncCell<T>, b: &RwSyncCell<T>, f: impl FnOnce(&T, &T) -> R) -> R {
+    if std::ptr::eq(a, b) {
+        return a.with(|value| f(value, value));
+    }
+    let (first, second) = if (a as *const RwSyncCell<T>) < (b as *const RwSyncCell<T>) {
+        (a, b)
+    } else {
+        (b, a)
+    };
+    let _first = first.lock.read().unwrap();
+    let _second = second.lock.read().unwrap();
+    //check both before arming either guard, so a panic here does not poison the other cell
+    if a.poisoned.load(Ordering::Relaxed) || b.poisoned.load(Ordering::Relaxed) {
+        poisoned();
+    }
+    let _poison = (a.unpoisoned(), b.unpoisoned());
+    //safe since we hold shared locks on both cells, so there is no writer
+    unsafe { f(a.inner.get(), b.inner.get()) }
+}
+
+impl<T: PartialEq> PartialEq for RwSyncCell<T> {
+    fn eq(&self, other: &Self) -> bool {
+        with_pair(self, other, |a, b| a == b)
+    }
+}
+
+impl<T: Eq> Eq for RwSyncCell<T> {}
+
+impl<T: PartialOrd> PartialOrd for RwSyncCell<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        with_pair(self, other, |a, b| a.partial_cmp(b))
+    }
+}
+
+impl<T: Ord> Ord for RwSyncCell<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        with_pair(self, other, |a, b| a.cmp(b))
+    }
+}
+
+impl<T: Hash> Hash for RwSyncCell<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.with(|value| value.hash(state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_basic_usage() {
+        let cell = RwSyncCell::new(42);
+        assert_eq!(cell.with(|value| *value * 2), 84);
+
+        cell.with_mut(|value| *value = 100);
+        assert_eq!(cell.with(|value| *value), 100);
+        assert_eq!(cell.into_inner(), 100);
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_traits() {
+        let cell = RwSyncCell::new(1);
+        assert_eq!(format!("{cell:?}"), "1");
+        assert_eq!(format!("{cell}"), "1");
+        assert_eq!(cell.clone(), RwSyncCell::from(1));
+        assert!(cell < RwSyncCell::new(2));
+        assert_eq!(RwSyncCell::<i32>::default().into_inner(), 0);
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_compare_with_self() {
+        let cell = RwSyncCell::new(1);
+        assert!(cell == cell);
+        assert_eq!(cell.cmp(&cell), std::cmp::Ordering::Equal);
+        //the value's own comparison decides, even against itself
+        let nan = RwSyncCell::new(f64::NAN);
+        assert!(nan != nan);
+        assert_eq!(nan.partial_cmp(&nan), None);
+    }
+
+    #[test]
+    fn test_compare_both_orders() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        use std::sync::Arc;
+
+        let a = Arc::new(RwSyncCell::new(vec![1; 64]));
+        let b = Arc::new(RwSyncCell::new(vec![2; 64]));
+        let comparers: Vec<_> = [(a.clone(), b.clone()), (b.clone(), a.clone())]
+            .into_iter()
+            .map(|(x, y)| {
+                std::thread::spawn(move || {
+                    for _ in 0..10_000 {
+                        assert!(x != y);
+                        assert!(x == x);
+                    }
+                })
+            })
+            .collect();
+        //queued writers block new readers, which deadlocks comparisons that lock out of order
+        let writers: Vec<_> = [a, b]
+            .into_iter()
+            .map(|cell| {
+                std::thread::spawn(move || {
+                    for _ in 0..10_000 {
+                        cell.with_mut(|value| value[0] += 2);
+                    }
+                })
+            })
+            .collect();
+        for thread in comparers.into_iter().chain(writers) {
+            thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_concurrent_readers() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        use std::sync::{Arc, Barrier};
+
+        let cell = Arc::new(RwSyncCell::new(7));
+        let barrier = Arc::new(Barrier::new(2));
+        let readers: Vec<_> = (0..2)
+            .map(|_| {
+                let cell = Arc::clone(&cell);
+                let barrier = Arc::clone(&barrier);
+                //both readers must be inside `with` at once to pass the barrier
+                std::thread::spawn(move || {
+                    cell.with(|value| {
+                        barrier.wait();
+                        *value
+                    })
+                })
+            })
+            .collect();
+        for reader in readers {
+            assert_eq!(reader.join().unwrap(), 7);
+        }
+    }
+
+    #[test]
+    //note: unwind tests are not supported in wasm
+    fn test_poisoning() {
+        let cell = RwSyncCell::new(42);
+
+        //a panicking reader poisons the cell, as with SyncCell
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cell.with(|_| panic!("test panic"));
+        }));
+        assert!(result.is_err());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| cell.with(|v| *v)));
+        assert!(result.is_err());
+        let result =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| cell.with_mut(|v| *v)));
+        assert!(result.is_err());
+
+        //and so does a panicking writer
+        let cell = RwSyncCell::new(42);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cell.with_mut(|_| panic!("test panic"));
+        }));
+        assert!(result.is_err());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| cell.with(|v| *v)));
+        assert!(result.is_err());
+    }
+}
@@ -17,9 +17,16 @@ from the wrong thread.
 # Thread Safety Model
 
 [`SendCell<T>`] remembers the thread it was created on and performs runtime checks on all access:
-- All methods except the `*_unchecked` variants will panic if called from a different thread
+- All methods except the `*_unchecked` and `try_*` variants will panic if called from a different thread
+- The `try_*` variants return a [`WrongThreadError`] instead of panicking
 - The cell can be moved between threads, but can only be accessed from its origin thread
 - Drop is also checked, ensuring the wrapped value is only dropped on the correct thread
+- Cells created with [`SendCell::new_deferring_drop`] hand a wrong-thread drop to the
+  origin thread instead of panicking (see [`crate::pending_drops`])
+- A [`ViolationPolicy`] can replace the panic with an abort, a leak, or a hook
+  (see [`crate::violation`])
+- A custom [`ThreadIdentity`] can bind the cell to a fiber or an executor instead of an
+  OS thread (see [`crate::identity`])
 
 # Example
 
@@ -66,12 +73,20 @@ requires_send_future(send_future);
 ```
 */
 
-use crate::sys::thread::ThreadId;
+use crate::dispatch::DispatchHandle;
+use crate::identity::{OsThread, ThreadIdentity, ThreadToken};
+use crate::pending_drops::PendingDrops;
+use crate::sys::thread::{Thread, ThreadId};
 use crate::unsafe_send_cell::UnsafeSendCell;
-use std::fmt::{Debug, Formatter};
+use crate::violation::{Operation, ViolationPolicy, ViolationReport};
+use std::any::Any;
+use std::fmt::{Debug, Display, Formatter};
 use std::future::Future;
+use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
+use std::panic::Location;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
 /// A runtime-checked cell that allows sending non-Send types between threads.
@@ -84,6 +99,10 @@ use std::task::{Context, Poll};
 /// checks on all operations. This makes it safe to use but comes with the cost of
 /// runtime panics if used incorrectly.
 ///
+/// The second parameter, `I`, is the [`ThreadIdentity`] that decides what "the same
+/// thread" means. It defaults to [`OsThread`]; cells with other identities are created
+/// with [`SendCell::new_bound`].
+///
 /// # Examples
 ///
 /// Basic usage with a non-Send type:
@@ -117,13 +136,147 @@ use std::task::{Context, Poll};
 ///
 /// # Panics
 ///
-/// All methods (except `*_unchecked` variants) will panic if called from a different
-/// thread than the one where the `SendCell` was created.
-pub struct SendCell<T> {
+/// All methods (except `*_unchecked` and `try_*` variants) will panic if called from a
+/// different thread than the one where the `SendCell` was created. The panic message
+/// names both threads and includes the source locations where the cell was created and
+/// where it was misused. With the `backtrace` feature, a backtrace of the creation is
+/// included as well when `RUST_BACKTRACE` is set.
+pub struct SendCell<T, I: ThreadIdentity = OsThread> {
     inner: Option<UnsafeSendCell<T>>,
-    thread_id: ThreadId,
+    origin: Origin<I>,
+    deferred_drop: Option<DeferredDrop<T>>,
+}
+
+/// The context a value is bound to, and how to react when it is used elsewhere.
+#[derive(Debug, Clone)]
+struct Origin<I> {
+    /// Compared on every access. For [`OsThread`], this is also how diagnostics find the
+    /// thread; see [`Self::thread`].
+    identity: I,
+    created_at: &'static Location<'static>,
+    #[cfg(feature = "backtrace")]
+    backtrace: Arc<std::backtrace::Backtrace>,
+    //None means the process-wide policy
+    policy: Option<ViolationPolicy>,
 }
 
+impl<I: ThreadIdentity> Origin<I> {
+    #[track_caller]
+    #[inline]
+    fn current() -> Origin<I> {
+        Origin {
+            identity: I::current(),
+            created_at: Location::caller(),
+            #[cfg(feature = "backtrace")]
+            backtrace: Arc::new(std::backtrace::Backtrace::capture()),
+            policy: None,
+        }
+    }
+
+    /// Verifies that the current context is the origin context.
+    #[inline]
+    fn check(&self) -> Result<(), WrongThreadError> {
+        if self.identity == I::current() {
+            crate::pending_drops::drain_if_pending();
+            Ok(())
+        } else {
+            Err(self.wrong_thread())
+        }
+    }
+
+    #[cold]
+    fn wrong_thread(&self) -> WrongThreadError {
+        WrongThreadError {
+            owner: self.thread().map(|thread| thread.id()),
+            current: crate::sys::thread::current().id(),
+            contexts: self.contexts(),
+        }
+    }
+
+    /// Returns the `Debug` output of the owning and the current context, for a custom
+    /// identity. Those can share an OS thread, so the threads alone would not explain a
+    /// violation.
+    fn contexts(&self) -> Option<(String, String)> {
+        if std::any::TypeId::of::<I>() == std::any::TypeId::of::<OsThread>() {
+            None
+        } else {
+            Some((
+                format!("{:?}", self.identity),
+                format!("{:?}", I::current()),
+            ))
+        }
+    }
+
+    /// Returns the OS thread the value is bound to, for diagnostics.
+    ///
+    /// Returns `None` if that thread has exited, or if the value is bound to a custom
+    /// identity rather than to an OS thread.
+    #[cold]
+    fn thread(&self) -> Option<Thread> {
+        let identity: &dyn Any = &self.identity;
+        let thread = identity.downcast_ref::<OsThread>()?;
+        crate::sys::thread_for_token(thread.0)
+    }
+
+    /// Reports the violation and applies the policy, returning only if the operation
+    /// may continue.
+    #[cold]
+    fn violation<T>(
+        &self,
+        operation: Operation,
+        location: Option<&'static Location<'static>>,
+    ) -> ViolationReport {
+        let report = ViolationReport::new(
+            operation,
+            std::any::type_name::<T>(),
+            self.thread(),
+            crate::sys::thread::current(),
+            self.created_at,
+            location,
+            #[cfg(feature = "backtrace")]
+            self.backtrace.clone(),
+        );
+        let report = match self.contexts() {
+            Some((owner, current)) => report.with_contexts(owner, current),
+            None => report,
+        };
+        crate::violation::handle(self.policy, &report);
+        report
+    }
+}
+
+/// Where to send the value if the cell is dropped on the wrong thread.
+struct DeferredDrop<T> {
+    queue: Arc<PendingDrops>,
+    push: DeferredPush<T>,
+}
+
+/// Functions that queue a value for deferred drop.
+///
+/// They are monomorphized where `T: 'static` is known, so `Drop` does not need the bound.
+struct DeferredPush<T> {
+    value: fn(&PendingDrops, UnsafeSendCell<T>),
+    //a SendFuture boxes its future, so it can be queued without moving it once pinned
+    pinned: fn(&PendingDrops, UnsafeSendCell<Pin<Box<T>>>),
+}
+
+impl<T: 'static> DeferredPush<T> {
+    fn new() -> Self {
+        DeferredPush {
+            value: PendingDrops::push::<T>,
+            pinned: PendingDrops::push::<Pin<Box<T>>>,
+        }
+    }
+}
+
+impl<T> Clone for DeferredPush<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for DeferredPush<T> {}
+
 impl<T> SendCell<T> {
     /// Creates a new `SendCell` wrapping the given value.
     ///
@@ -144,11 +297,231 @@ impl<T> SendCell<T> {
     /// println!("{}", cell.get());
     /// ```
     #[inline]
+    #[track_caller]
     pub fn new(t: T) -> SendCell<T> {
+        crate::pending_drops::drain_if_pending();
         SendCell {
             //safe because drop is verified
             inner: Some(unsafe { UnsafeSendCell::new_unchecked(t) }),
-            thread_id: crate::sys::thread::current().id(),
+            origin: Origin::current(),
+            deferred_drop: None,
+        }
+    }
+
+    /// Returns a builder for configuring a new `SendCell` wrapping the given value.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::{SendCell, ViolationPolicy};
+    /// use std::rc::Rc;
+    ///
+    /// let cell = SendCell::builder(Rc::new(42))
+    ///     .policy(ViolationPolicy::LeakAndContinue)
+    ///     .deferring_drop()
+    ///     .build();
+    /// assert_eq!(**cell.get(), 42);
+    /// ```
+    pub fn builder(t: T) -> SendCellBuilder<T> {
+        SendCell::builder_bound(t)
+    }
+
+    /// Creates a new `SendCell` that defers a wrong-thread drop to its origin thread.
+    ///
+    /// Access is checked exactly as for [`Self::new`]. The difference is in `Drop`: if
+    /// the cell is dropped on another thread, the wrapped value is queued on the origin
+    /// thread instead of panicking. The queue is drained the next time the origin thread
+    /// touches any `SendCell`, calls [`crate::drain_pending_drops`], or exits. Values
+    /// deferred after the origin thread has exited are leaked.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SendCell;
+    /// use std::rc::Rc;
+    ///
+    /// let cell = SendCell::new_deferring_drop(Rc::new(42));
+    ///
+    /// // Would panic with `SendCell::new`
+    /// std::thread::spawn(move || drop(cell)).join().unwrap();
+    ///
+    /// // The Rc is dropped here, on the origin thread
+    /// assert_eq!(send_cells::drain_pending_drops(), 1);
+    /// ```
+    #[track_caller]
+    pub fn new_deferring_drop(t: T) -> SendCell<T>
+    where
+        T: 'static,
+    {
+        SendCell::builder(t).deferring_drop().build()
+    }
+
+    /// Checks that the current thread is the cell's origin thread, and returns a token
+    /// proving it.
+    ///
+    /// The token can then be passed to [`Self::get_with`] on this cell or any other cell
+    /// created on the same thread. This is the same as [`ThreadToken::current`], except
+    /// that it panics on the wrong thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from a different thread than the one where this `SendCell`
+    /// was created.
+    #[inline]
+    #[track_caller]
+    pub fn token(&self) -> ThreadToken {
+        let token = ThreadToken::current();
+        if self.origin.identity != token.thread {
+            let report = self
+                .origin
+                .violation::<T>(Operation::Get, Some(Location::caller()));
+            panic!("{report}")
+        }
+        token
+    }
+
+    /// Accesses the underlying value, checking the thread against `token`.
+    ///
+    /// This is [`Self::get`] without the thread-local read: since a [`ThreadToken`] never
+    /// leaves its thread, comparing it with the cell's origin is enough.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `token` is for a different thread than the one where this `SendCell`
+    /// was created.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SendCell;
+    /// use std::rc::Rc;
+    ///
+    /// let a = SendCell::new(Rc::new(1));
+    /// let b = SendCell::new(Rc::new(2));
+    /// let token = a.token();
+    /// assert_eq!(**a.get_with(&token) + **b.get_with(&token), 3);
+    /// ```
+    #[inline]
+    #[track_caller]
+    pub fn get_with(&self, token: &ThreadToken) -> &T {
+        if self.origin.identity != token.thread {
+            let report = self
+                .origin
+                .violation::<T>(Operation::Get, Some(Location::caller()));
+            panic!("{report}")
+        }
+        //safe because the token proves we are on the origin thread
+        unsafe { self.get_unchecked() }
+    }
+
+    /// Accesses the underlying value mutably, checking the thread against `token`.
+    ///
+    /// This is [`Self::get_mut`] without the thread-local read; see [`Self::get_with`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `token` is for a different thread than the one where this `SendCell`
+    /// was created.
+    #[inline]
+    #[track_caller]
+    pub fn get_mut_with(&mut self, token: &ThreadToken) -> &mut T {
+        if self.origin.identity != token.thread {
+            let report = self
+                .origin
+                .violation::<T>(Operation::GetMut, Some(Location::caller()));
+            panic!("{report}")
+        }
+        unsafe { self.get_unchecked_mut() }
+    }
+
+    /// Runs `f` with the wrapped value on the cell's origin thread, from any thread.
+    ///
+    /// The cell is moved to its origin thread along with `f`, which runs the next time
+    /// that thread pumps its [`crate::dispatch::ThreadDispatcher`]. The returned
+    /// [`DispatchHandle`] hands the cell back together with the result once `f` has run.
+    /// When called on the origin thread itself, `f` runs immediately.
+    ///
+    /// # Errors
+    ///
+    /// Returns the cell unchanged if the origin thread has no registered dispatcher.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SendCell;
+    /// use std::rc::Rc;
+    ///
+    /// // On the origin thread, the closure runs right away
+    /// let cell = SendCell::new(Rc::new(1));
+    /// let (cell, value) = cell.dispatch(|rc| **rc + 1).ok().unwrap().wait().unwrap();
+    /// assert_eq!(value, 2);
+    ///
+    /// // Elsewhere, the origin thread needs a dispatcher; this one has none
+    /// let cell = std::thread::spawn(move || cell.dispatch(|rc| **rc).unwrap_err())
+    ///     .join()
+    ///     .unwrap();
+    /// assert_eq!(**cell.get(), 1);
+    /// ```
+    pub fn dispatch<R, F>(self, f: F) -> Result<DispatchHandle<T, R>, SendCell<T>>
+    where
+        T: 'static,
+        R: Send + 'static,
+        F: FnOnce(&mut T) -> R + Send + 'static,
+    {
+        crate::dispatch::dispatch(self.origin.identity, self, f)
+    }
+}
+
+impl<T, I: ThreadIdentity> SendCell<T, I> {
+    /// Creates a new `SendCell` bound to the current context of identity `I`.
+    ///
+    /// This is [`SendCell::new`] for cells with a custom [`ThreadIdentity`]: the cell
+    /// remembers `I::current()`, and every access is checked against it. Such cells
+    /// cannot defer a wrong-thread drop, so the violation policy always applies; use
+    /// [`Self::builder_bound`] to set one for the cell.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::{OsThread, SendCell};
+    ///
+    /// // With the default identity, this is the same as `SendCell::new`
+    /// let cell = SendCell::<_, OsThread>::new_bound(42);
+    /// assert_eq!(*cell.get(), 42);
+    /// ```
+    #[inline]
+    #[track_caller]
+    pub fn new_bound(t: T) -> SendCell<T, I> {
+        crate::pending_drops::drain_if_pending();
+        SendCell {
+            //safe because drop is verified
+            inner: Some(unsafe { UnsafeSendCell::new_unchecked(t) }),
+            origin: Origin::current(),
+            deferred_drop: None,
+        }
+    }
+
+    /// Returns a builder for a new `SendCell` bound to the current context of identity `I`.
+    ///
+    /// This is [`SendCell::builder`] for cells with a custom [`ThreadIdentity`]. Only the
+    /// violation policy can be configured; deferring drops needs [`OsThread`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::{ExecutorCell, ViolationPolicy};
+    ///
+    /// let cell = ExecutorCell::builder_bound(42)
+    ///     .policy(ViolationPolicy::LeakAndContinue)
+    ///     .build();
+    /// assert_eq!(*cell.get(), 42);
+    /// ```
+    pub fn builder_bound(t: T) -> SendCellBuilder<T, I> {
+        SendCellBuilder {
+            value: t,
+            policy: None,
+            deferred_drop: None,
+            _identity: PhantomData,
         }
     }
 
@@ -204,14 +577,49 @@ impl<T> SendCell<T> {
     /// assert_eq!(value, Some(&"value"));
     /// ```
     #[inline]
+    #[track_caller]
     pub fn get(&self) -> &T {
-        assert_eq!(
-            self.thread_id,
-            crate::sys::thread::current().id(),
-            "Access SendCell from incorrect thread"
-        );
-        //safe with assertion
-        unsafe { self.get_unchecked() }
+        match self.try_get() {
+            Ok(value) => value,
+            Err(_) => {
+                let report = self
+                    .origin
+                    .violation::<T>(Operation::Get, Some(Location::caller()));
+                panic!("{report}")
+            }
+        }
+    }
+
+    /// Accesses the underlying value, returning an error if called from the wrong thread.
+    ///
+    /// This is the non-panicking counterpart of [`Self::get`]. It allows callers to branch
+    /// on a thread mismatch without unwinding.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`WrongThreadError`] if called from a different thread than the one where
+    /// this `SendCell` was created.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SendCell;
+    /// use std::rc::Rc;
+    ///
+    /// let cell = SendCell::new(Rc::new(42));
+    /// assert_eq!(**cell.try_get().unwrap(), 42);
+    ///
+    /// let cell = std::thread::spawn(move || {
+    ///     assert!(cell.try_get().is_err());
+    ///     cell
+    /// }).join().unwrap();
+    /// assert!(cell.try_get().is_ok());
+    /// ```
+    #[inline]
+    pub fn try_get(&self) -> Result<&T, WrongThreadError> {
+        self.origin.check()?;
+        //safe with check
+        Ok(unsafe { self.get_unchecked() })
     }
 
     /// Unsafely accesses the underlying value mutably without thread checking.
@@ -268,15 +676,41 @@ impl<T> SendCell<T> {
     /// assert_eq!(cell.get().get("key"), Some(&"value"));
     /// ```
     #[inline]
+    #[track_caller]
     pub fn get_mut(&mut self) -> &mut T {
-        assert_eq!(
-            self.thread_id,
-            crate::sys::thread::current().id(),
-            "Access SendCell from incorrect thread"
-        );
+        if self.origin.check().is_err() {
+            let report = self
+                .origin
+                .violation::<T>(Operation::GetMut, Some(Location::caller()));
+            panic!("{report}")
+        }
         unsafe { self.get_unchecked_mut() }
     }
 
+    /// Accesses the underlying value mutably, returning an error if called from the wrong thread.
+    ///
+    /// This is the non-panicking counterpart of [`Self::get_mut`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`WrongThreadError`] if called from a different thread than the one where
+    /// this `SendCell` was created.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SendCell;
+    ///
+    /// let mut cell = SendCell::new(vec![1, 2, 3]);
+    /// cell.try_get_mut().unwrap().push(4);
+    /// assert_eq!(cell.get().len(), 4);
+    /// ```
+    #[inline]
+    pub fn try_get_mut(&mut self) -> Result<&mut T, WrongThreadError> {
+        self.origin.check()?;
+        Ok(unsafe { self.get_unchecked_mut() })
+    }
+
     /// Unsafely consumes the cell and returns the wrapped value without thread checking.
     ///
     /// # Safety
@@ -328,9 +762,54 @@ impl<T> SendCell<T> {
     /// assert_eq!(*recovered_data, "Hello, world!");
     /// ```
     #[inline]
+    #[track_caller]
     pub fn into_inner(self) -> T {
-        assert_eq!(self.thread_id, crate::sys::thread::current().id());
-        unsafe { self.into_unchecked_inner() }
+        match self.try_into_inner() {
+            Ok(value) => value,
+            Err(mut cell) => {
+                //leak first: dropping the value while unwinding would panic again and abort
+                std::mem::forget(cell.inner.take());
+                let report = cell
+                    .origin
+                    .violation::<T>(Operation::IntoInner, Some(Location::caller()));
+                panic!("{report}")
+            }
+        }
+    }
+
+    /// Consumes the cell and returns the wrapped value, or gives the cell back if
+    /// called from the wrong thread.
+    ///
+    /// This is the non-panicking counterpart of [`Self::into_inner`]. On failure the
+    /// cell is returned unchanged, so it can be moved back to its origin thread.
+    ///
+    /// # Errors
+    ///
+    /// Returns the original `SendCell` if called from a different thread than the one
+    /// where it was created.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SendCell;
+    /// use std::rc::Rc;
+    ///
+    /// let cell = SendCell::new(Rc::new(42));
+    ///
+    /// let cell = std::thread::spawn(move || {
+    ///     // Wrong thread: we get the cell back
+    ///     cell.try_into_inner().unwrap_err()
+    /// }).join().unwrap();
+    ///
+    /// // Origin thread: we get the value
+    /// assert_eq!(*cell.try_into_inner().unwrap(), 42);
+    /// ```
+    #[inline]
+    pub fn try_into_inner(self) -> Result<T, SendCell<T, I>> {
+        match self.origin.check() {
+            Ok(()) => Ok(unsafe { self.into_unchecked_inner() }),
+            Err(_) => Err(self),
+        }
     }
 
     /// Creates a new cell with a different value, preserving the thread affinity.
@@ -363,11 +842,16 @@ impl<T> SendCell<T> {
     /// assert_eq!(derived.get(), "Hello");
     /// ```
     #[inline]
-    pub unsafe fn preserving_cell_thread<U>(&self, new: U) -> SendCell<U> {
+    #[track_caller]
+    pub unsafe fn preserving_cell_thread<U>(&self, new: U) -> SendCell<U, I> {
         unsafe {
             SendCell {
                 inner: Some(UnsafeSendCell::new_unchecked(new)),
-                thread_id: self.thread_id,
+                origin: Origin {
+                    created_at: Location::caller(),
+                    ..self.origin.clone()
+                },
+                deferred_drop: None,
             }
         }
     }
@@ -392,6 +876,7 @@ impl<T> SendCell<T> {
     /// std::mem::drop(original);
     /// assert_eq!(*copied.get(), 42);
     /// ```
+    #[track_caller]
     pub fn copying(&self) -> Self
     where
         T: Copy,
@@ -400,13 +885,17 @@ impl<T> SendCell<T> {
     }
 }
 
-impl<T: Future> SendCell<T> {
+impl<T: Future, I: ThreadIdentity> SendCell<T, I> {
     /// Converts the cell into a future that implements Send with runtime thread checking.
     ///
     /// This method consumes the `SendCell` and returns a [`SendFuture`] that implements
     /// `Send` and can be moved between threads. However, the future will panic if polled
     /// from a different thread than the one where the original `SendCell` was created.
     ///
+    /// The future inherits the cell's [`ViolationPolicy`]. It is boxed, so that a
+    /// wrong-thread drop (for example, cancellation on another executor thread) can defer
+    /// or leak it without moving it, even after it has been pinned and polled.
+    ///
     /// Unlike [`crate::UnsafeSendCell::into_future()`], this provides memory safety
     /// through runtime checks.
     ///
@@ -435,54 +924,207 @@ impl<T: Future> SendCell<T> {
     /// fn assert_send<T: Send>(_: T) {}
     /// assert_send(send_future);
     /// ```
-    pub fn into_future(mut self) -> SendFuture<T> {
+    #[track_caller]
+    pub fn into_future(mut self) -> SendFuture<T, I> {
+        //safe because the value is only moved, not accessed
+        let future = unsafe { self.inner.take().expect("inner value missing").into_inner() };
         SendFuture {
-            inner: self.inner.take().expect("inner value missing"),
-            thread_id: self.thread_id,
+            //safe because drop is verified
+            inner: Some(unsafe { UnsafeSendCell::new_unchecked(Box::pin(future)) }),
+            origin: Origin {
+                created_at: Location::caller(),
+                ..self.origin.clone()
+            },
+            deferred_drop: self.deferred_drop.take(),
         }
     }
 }
 
-impl<T> Drop for SendCell<T> {
-    fn drop(&mut self) {
-        if std::mem::needs_drop::<T>() {
-            assert_eq!(
-                self.thread_id,
-                crate::sys::thread::current().id(),
-                "Drop SendCell from incorrect thread"
+/// The error returned when a [`SendCell`] is accessed from a thread other than its origin.
+///
+/// Returned by [`SendCell::try_get`] and [`SendCell::try_get_mut`]. It records both the
+/// thread that owns the cell and the thread that attempted the access, and, for a cell
+/// bound to a custom [`ThreadIdentity`], both contexts.
+///
+/// # Examples
+///
+/// ```rust
+/// use send_cells::SendCell;
+///
+/// let cell = SendCell::new(42);
+/// let owner = std::thread::current().id();
+///
+/// std::thread::spawn(move || {
+///     let e = cell.try_get().unwrap_err();
+///     assert_eq!(e.owner(), Some(owner));
+///     assert_eq!(e.current(), std::thread::current().id());
+/// }).join().unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrongThreadError {
+    owner: Option<ThreadId>,
+    current: ThreadId,
+    //owner and current context, for cells with a custom identity
+    contexts: Option<(String, String)>,
+}
+
+impl WrongThreadError {
+    pub(crate) fn new(owner: Option<ThreadId>, current: ThreadId) -> Self {
+        WrongThreadError {
+            owner,
+            current,
+            contexts: None,
+        }
+    }
+
+    /// Returns the id of the thread the cell was created on.
+    ///
+    /// Cells only keep a cheap token for their thread, so this is `None` once that thread
+    /// has exited. It is also `None` for cells bound to a custom [`ThreadIdentity`].
+    pub fn owner(&self) -> Option<ThreadId> {
+        self.owner
+    }
+
+    /// Returns the id of the thread that attempted the access.
+    pub fn current(&self) -> ThreadId {
+        self.current
+    }
+
+    /// Returns the `Debug` representations of the owning and the current context, for cells
+    /// bound with a custom [`ThreadIdentity`].
+    ///
+    /// Such contexts can share an OS thread, so they are what tells them apart. Returns
+    /// `None` for cells bound to an OS thread.
+    pub fn contexts(&self) -> Option<(&str, &str)> {
+        self.contexts
+            .as_ref()
+            .map(|(owner, current)| (owner.as_str(), current.as_str()))
+    }
+}
+
+impl Display for WrongThreadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some((owner, current)) = &self.contexts {
+            return write!(
+                f,
+                "Access SendCell from incorrect execution context \
+                 (owner: {owner}, current: {current}, on thread {:?})",
+                self.current
             );
         }
+        match self.owner {
+            Some(owner) => write!(
+                f,
+                "Access SendCell from incorrect thread (owner: {owner:?}, current: {:?})",
+                self.current
+            ),
+            None => write!(
+                f,
+                "Access SendCell from incorrect thread (owner: unknown, current: {:?})",
+                self.current
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WrongThreadError {}
+
+impl<T, I: ThreadIdentity> Drop for SendCell<T, I> {
+    fn drop(&mut self) {
+        if std::mem::needs_drop::<T>() && self.inner.is_some() && self.origin.check().is_err() {
+            let inner = self.inner.take().unwrap();
+            match &self.deferred_drop {
+                Some(deferred) => (deferred.push.value)(&deferred.queue, inner),
+                None => {
+                    //leak first: if the policy panics, the value must not be dropped while unwinding
+                    std::mem::forget(inner);
+                    self.origin.violation::<T>(Operation::Drop, None);
+                }
+            }
+        }
+    }
+}
+
+/// A builder for [`SendCell`] with a non-default configuration.
+///
+/// Created by [`SendCell::builder`], or by [`SendCell::builder_bound`] for a custom
+/// [`ThreadIdentity`].
+pub struct SendCellBuilder<T, I: ThreadIdentity = OsThread> {
+    value: T,
+    policy: Option<ViolationPolicy>,
+    //only ever set for OsThread
+    deferred_drop: Option<DeferredPush<T>>,
+    _identity: PhantomData<I>,
+}
+
+impl<T, I: ThreadIdentity> SendCellBuilder<T, I> {
+    /// Sets the policy applied when the cell is used from the wrong thread.
+    ///
+    /// If not set, the process-wide policy from [`crate::set_violation_policy`] is used.
+    pub fn policy(mut self, policy: ViolationPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Creates the cell, bound to the current context.
+    #[track_caller]
+    pub fn build(self) -> SendCell<T, I> {
+        let mut cell = SendCell::new_bound(self.value);
+        cell.origin.policy = self.policy;
+        cell.deferred_drop = self.deferred_drop.map(|push| DeferredDrop {
+            queue: crate::pending_drops::current_queue(),
+            push,
+        });
+        cell
+    }
+}
+
+impl<T> SendCellBuilder<T> {
+    /// Defers a wrong-thread drop to the origin thread, as [`SendCell::new_deferring_drop`].
+    ///
+    /// Deferral takes precedence over the violation policy for drops.
+    pub fn deferring_drop(mut self) -> Self
+    where
+        T: 'static,
+    {
+        self.deferred_drop = Some(DeferredPush::new());
+        self
     }
 }
 
 // Trait implementations that delegate to the wrapped value
 // All of these perform runtime thread checking through get() and get_mut()
-impl<T: Debug> Debug for SendCell<T> {
+impl<T: Debug, I: ThreadIdentity> Debug for SendCell<T, I> {
+    #[track_caller]
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         self.get().fmt(f)
     }
 }
 
-impl<T> AsRef<T> for SendCell<T> {
+impl<T, I: ThreadIdentity> AsRef<T> for SendCell<T, I> {
+    #[track_caller]
     fn as_ref(&self) -> &T {
         self.get()
     }
 }
 
-impl<T> AsMut<T> for SendCell<T> {
+impl<T, I: ThreadIdentity> AsMut<T> for SendCell<T, I> {
+    #[track_caller]
     fn as_mut(&mut self) -> &mut T {
         self.get_mut()
     }
 }
 
-impl<T> Deref for SendCell<T> {
+impl<T, I: ThreadIdentity> Deref for SendCell<T, I> {
     type Target = T;
+    #[track_caller]
     fn deref(&self) -> &Self::Target {
         self.get()
     }
 }
 
-impl<T> DerefMut for SendCell<T> {
+impl<T, I: ThreadIdentity> DerefMut for SendCell<T, I> {
+    #[track_caller]
     fn deref_mut(&mut self) -> &mut Self::Target {
         self.get_mut()
     }
@@ -490,14 +1132,16 @@ impl<T> DerefMut for SendCell<T> {
 
 // Additional trait implementations
 // For comparison traits (Eq, Hash, etc.), we rely on Deref to the underlying type
-impl<T: Default> Default for SendCell<T> {
-    fn default() -> SendCell<T> {
-        SendCell::new(Default::default())
+impl<T: Default, I: ThreadIdentity> Default for SendCell<T, I> {
+    #[track_caller]
+    fn default() -> SendCell<T, I> {
+        SendCell::new_bound(Default::default())
     }
 }
-impl<T> From<T> for SendCell<T> {
+impl<T, I: ThreadIdentity> From<T> for SendCell<T, I> {
+    #[track_caller]
     fn from(value: T) -> Self {
-        SendCell::new(value)
+        SendCell::new_bound(value)
     }
 }
 
@@ -546,36 +1190,71 @@ impl<T> From<T> for SendCell<T> {
 /// # Panics
 ///
 /// The `poll` method will panic if called from a different thread than the one
-/// where the original `SendCell` was created.
-#[derive(Debug)]
-pub struct SendFuture<T> {
-    inner: UnsafeSendCell<T>,
-    thread_id: ThreadId,
+/// where the original `SendCell` was created, unless a [`ViolationPolicy`] says otherwise.
+///
+/// Dropping the future on a different thread (for example, when a task is cancelled on
+/// another executor thread) is checked the same way as for [`SendCell`], and can be
+/// deferred to the origin thread with [`SendCell::new_deferring_drop`].
+pub struct SendFuture<T, I: ThreadIdentity = OsThread> {
+    //boxed, so that a wrong-thread drop can queue or leak the future without moving it
+    inner: Option<UnsafeSendCell<Pin<Box<T>>>>,
+    origin: Origin<I>,
+    deferred_drop: Option<DeferredDrop<T>>,
+}
+
+impl<T, I: ThreadIdentity> Debug for SendFuture<T, I> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SendFuture")
+            .field("inner", &self.inner)
+            .field("origin", &self.origin)
+            .finish_non_exhaustive()
+    }
 }
 
 // SAFETY: SendFuture implements Send by providing runtime thread checking.
 // The wrapped future may not be Send, but we ensure safety by panicking
 // if poll() is called from the wrong thread.
-unsafe impl<T> Send for SendFuture<T> {}
+unsafe impl<T, I: ThreadIdentity> Send for SendFuture<T, I> {}
 
-impl<T: Future> Future for SendFuture<T> {
+impl<T: Future, I: ThreadIdentity> Future for SendFuture<T, I> {
     type Output = T::Output;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        // Runtime thread check - panic if called from wrong thread
-        assert_eq!(
-            self.thread_id,
-            crate::sys::thread::current().id(),
-            "SendFuture polled from incorrect thread"
-        );
+        // Runtime thread check - apply the policy if called from wrong thread
+        if self.origin.check().is_err() {
+            self.origin.violation::<T>(Operation::Poll, None);
+            //the policy allows continuing, but the future can never make progress here
+            return Poll::Pending;
+        }
 
         // SAFETY: After the thread check, we can safely access the inner future
         // using the same technique as UnsafeSendFuture
-        let inner = unsafe {
+        let future = unsafe {
             let self_mut = self.get_unchecked_mut();
-            Pin::new_unchecked(self_mut.inner.get_mut())
+            self_mut.inner.as_mut().expect("gone").get_mut()
         };
-        inner.poll(cx)
+        future.as_mut().poll(cx)
+    }
+}
+
+impl<T, I: ThreadIdentity> Drop for SendFuture<T, I> {
+    fn drop(&mut self) {
+        if std::mem::needs_drop::<T>() && self.inner.is_some() && self.origin.check().is_err() {
+            //safe because the future is only queued or leaked, never accessed
+            let future = unsafe { self.inner.take().unwrap().into_inner() };
+            match &self.deferred_drop {
+                Some(deferred) => {
+                    let future = unsafe { UnsafeSendCell::new_unchecked(future) };
+                    (deferred.push.pinned)(&deferred.queue, future)
+                }
+                None => {
+                    //leak first: if the policy panics, the future must not be dropped while unwinding.
+                    //Forgetting the box never frees the pinned memory, as `Pin` requires.
+                    std::mem::forget(future);
+                    self.origin.violation::<T>(Operation::Drop, None);
+                }
+            }
+        }
     }
 }
 
@@ -666,6 +1345,238 @@ mod tests {
         }
     }
 
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_try_access_same_thread() {
+        let mut cell = SendCell::new(Rc::new(42));
+        assert_eq!(**cell.try_get().unwrap(), 42);
+        *cell.try_get_mut().unwrap() = Rc::new(43);
+        let value = cell.try_into_inner().ok().unwrap();
+        assert_eq!(*value, 43);
+    }
+
+    #[test]
+    //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+    fn test_try_access_wrong_thread() {
+        use crate::sys::thread;
+
+        let owner = thread::current().id();
+        let cell = SendCell::new(Rc::new(42));
+        let cell = thread::spawn(move || {
+            let mut cell = cell;
+            let current = thread::current().id();
+            let e = cell.try_get().unwrap_err();
+            assert_eq!(e.owner(), Some(owner));
+            assert_eq!(e.current(), current);
+            assert_eq!(cell.try_get_mut().unwrap_err(), e);
+            cell.try_into_inner().unwrap_err()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(*cell.into_inner(), 42);
+    }
+
+    #[test]
+    //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+    fn test_owner_unknown_after_thread_exits() {
+        use crate::sys::thread;
+
+        //Cell<i32> has no destructor, so dropping it here is not a violation
+        let cell = thread::spawn(|| SendCell::new(std::cell::Cell::new(1)))
+            .join()
+            .unwrap();
+        let e = cell.try_get().unwrap_err();
+        assert_eq!(e.owner(), None);
+        assert_eq!(e.current(), thread::current().id());
+    }
+
+    #[test]
+    //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+    fn test_thread_token() {
+        use crate::sys::thread;
+
+        let mut cell = SendCell::new(Rc::new(1));
+        let token = cell.token();
+        *cell.get_mut_with(&token) = Rc::new(2);
+        assert_eq!(**cell.get_with(&token), 2);
+
+        let cell = thread::spawn(move || {
+            //note: unwind tests are not supported in wasm
+            let token = ThreadToken::current();
+            let wrong_token =
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| **cell.get_with(&token)));
+            let wrong_cell =
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| cell.token()));
+            assert!(wrong_token.is_err() && wrong_cell.is_err());
+            cell
+        })
+        .join()
+        .unwrap();
+        assert_eq!(**cell.get_with(&token), 2);
+    }
+
+    #[test]
+    //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+    fn test_deferred_drop_runs_on_origin_thread() {
+        use crate::sys::thread;
+        use std::sync::Mutex;
+
+        struct Tracked(
+            std::marker::PhantomData<Rc<()>>,
+            Arc<Mutex<Option<ThreadId>>>,
+        );
+        impl Drop for Tracked {
+            fn drop(&mut self) {
+                *self.1.lock().unwrap() = Some(thread::current().id());
+            }
+        }
+
+        let dropped_on = Arc::new(Mutex::new(None));
+        let cell =
+            SendCell::new_deferring_drop(Tracked(std::marker::PhantomData, dropped_on.clone()));
+        thread::spawn(move || drop(cell)).join().unwrap();
+        assert_eq!(*dropped_on.lock().unwrap(), None);
+
+        // Touching any SendCell on the origin thread drains the queue
+        let other = SendCell::new(Rc::new(1));
+        assert_eq!(**other.get(), 1);
+        assert_eq!(*dropped_on.lock().unwrap(), Some(thread::current().id()));
+        assert_eq!(crate::drain_pending_drops(), 0);
+    }
+
+    #[test]
+    //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+    fn test_deferred_drop_after_origin_thread_exits() {
+        use crate::sys::thread;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        struct Tracked(std::marker::PhantomData<Rc<()>>, Arc<AtomicBool>);
+        impl Drop for Tracked {
+            fn drop(&mut self) {
+                self.1.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let dropped = Arc::new(AtomicBool::new(false));
+        let dropped_clone = dropped.clone();
+        let cell = thread::spawn(move || {
+            SendCell::new_deferring_drop(Tracked(std::marker::PhantomData, dropped_clone))
+        })
+        .join()
+        .unwrap();
+        // The origin thread is gone, so the value is leaked rather than dropped here
+        drop(cell);
+        assert!(!dropped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+    fn test_panic_message_describes_violation() {
+        use crate::sys::thread;
+
+        let created_at = format!("{}:{}", file!(), line!() + 1);
+        let cell = SendCell::new(Rc::new(42));
+        let payload = thread::Builder::new()
+            .name("wrong-thread".to_string())
+            .spawn(move || {
+                let result = std::panic::catch_unwind(|| {
+                    let _ = cell.get();
+                });
+                std::mem::forget(cell);
+                result.unwrap_err()
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        let message = payload.downcast_ref::<String>().unwrap();
+        assert!(message.starts_with("Access SendCell from incorrect thread"));
+        assert!(message.contains("alloc::rc::Rc<i32>"));
+        assert!(message.contains(&format!("{:?}", thread::current().name().unwrap())));
+        assert!(message.contains("\"wrong-thread\""));
+        assert!(message.contains(&format!("created at {created_at}")));
+        assert!(message.contains(&format!("used at {}", file!())));
+    }
+
+    // A non-Send future that records the thread it was dropped on
+    struct DropTrackingFuture {
+        _data: std::marker::PhantomData<Rc<()>>,
+        dropped_on: Arc<std::sync::Mutex<Option<ThreadId>>>,
+    }
+
+    impl Future for DropTrackingFuture {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+            Poll::Pending
+        }
+    }
+
+    impl Drop for DropTrackingFuture {
+        fn drop(&mut self) {
+            *self.dropped_on.lock().unwrap() = Some(crate::sys::thread::current().id());
+        }
+    }
+
+    fn cancel_on_other_thread(deferring: bool, poll_first: bool) -> (bool, Option<ThreadId>) {
+        use crate::sys::thread;
+
+        let dropped_on = Arc::new(std::sync::Mutex::new(None));
+        let future = DropTrackingFuture {
+            _data: std::marker::PhantomData,
+            dropped_on: dropped_on.clone(),
+        };
+        let cell = if deferring {
+            SendCell::new_deferring_drop(future)
+        } else {
+            SendCell::new(future)
+        };
+        let mut send_future = Box::pin(cell.into_future());
+        if poll_first {
+            let mut context = Context::from_waker(Waker::noop());
+            assert_eq!(send_future.as_mut().poll(&mut context), Poll::Pending);
+        }
+        let panicked = thread::spawn(move || drop(send_future)).join().is_err();
+        crate::drain_pending_drops();
+        let dropped_on = *dropped_on.lock().unwrap();
+        (panicked, dropped_on)
+    }
+
+    #[test]
+    //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+    fn test_send_future_cancel_unpolled_on_wrong_thread() {
+        assert_eq!(cancel_on_other_thread(false, false), (true, None));
+    }
+
+    #[test]
+    //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+    fn test_send_future_cancel_polled_on_wrong_thread() {
+        assert_eq!(cancel_on_other_thread(false, true), (true, None));
+    }
+
+    #[test]
+    //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+    fn test_send_future_deferred_cancel() {
+        let origin = crate::sys::thread::current().id();
+        assert_eq!(cancel_on_other_thread(true, false), (false, Some(origin)));
+        assert_eq!(cancel_on_other_thread(true, true), (false, Some(origin)));
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_send_future_drop_on_origin_thread() {
+        let dropped_on = Arc::new(std::sync::Mutex::new(None));
+        let future = SendCell::new(DropTrackingFuture {
+            _data: std::marker::PhantomData,
+            dropped_on: dropped_on.clone(),
+        })
+        .into_future();
+        drop(future);
+        assert_eq!(
+            *dropped_on.lock().unwrap(),
+            Some(crate::sys::thread::current().id())
+        );
+    }
+
     //no unwind on wasm!
     #[test]
     //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
@@ -707,4 +1618,48 @@ mod tests {
             "Expected thread to panic when polling SendFuture from incorrect thread"
         );
     }
+
+    thread_local! {
+        static CURRENT_FIBER: std::cell::Cell<u32> = const { std::cell::Cell::new(0) };
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Fiber(ThreadId, u32);
+
+    // SAFETY: fibers in these tests never leave their thread
+    unsafe impl ThreadIdentity for Fiber {
+        fn current() -> Self {
+            Fiber(
+                crate::sys::thread::current().id(),
+                CURRENT_FIBER.with(|fiber| fiber.get()),
+            )
+        }
+    }
+
+    #[test]
+    //note: unwind tests are not supported in wasm
+    fn test_custom_identity() {
+        let mut cell = SendCell::<_, Fiber>::new_bound(Rc::new(1));
+        *cell.get_mut() = Rc::new(2);
+        let future = SendCell::<_, Fiber>::new_bound(NonSendFuture::new(0)).into_future();
+
+        CURRENT_FIBER.with(|fiber| fiber.set(1));
+        //both fibers run on this thread, so the error names the contexts
+        let e = cell.try_get().unwrap_err();
+        assert_eq!(e.owner(), None);
+        let (owner, current) = e.contexts().unwrap();
+        assert!(owner.ends_with("0)") && current.ends_with("1)"), "{e}");
+        assert!(e.to_string().contains("incorrect execution context"), "{e}");
+        let panic =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| **cell.get())).unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+        assert!(message.contains("context Fiber("), "{message}");
+
+        CURRENT_FIBER.with(|fiber| fiber.set(0));
+        assert_eq!(**cell.get(), 2);
+        let mut future = Box::pin(future);
+        let mut context = Context::from_waker(Waker::noop());
+        assert_eq!(future.as_mut().poll(&mut context), Poll::Pending);
+        assert_eq!(future.as_mut().poll(&mut context), Poll::Ready(42));
+    }
 }
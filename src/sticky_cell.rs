@@ -0,0 +1,403 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+/*!
+A cell that keeps its value in thread-local storage, so it can be dropped anywhere.
+
+This module provides [`StickyCell<T>`]. At construction the value is moved into a registry
+owned by the current thread, and the cell keeps only a key to it. Since the value never
+leaves its thread, the cell is `Send + Sync` and can be dropped from any thread without
+panicking, which is the main difference from [`crate::SendCell`].
+
+# Thread Safety Model
+
+- The value is only reachable on the thread that created the cell; access is through
+  closures, so no reference can outlive the call
+- Access from any other thread is a violation, reported through the process-wide
+  [`crate::ViolationPolicy`] and hook (see [`crate::violation`])
+- Dropping the cell on the owner thread drops the value immediately
+- Dropping the cell on any other thread only forgets the key; the value stays in the
+  owner's registry and is dropped when the owner thread exits
+- Since values dropped elsewhere live until their thread exits, `StickyCell` is best
+  suited for threads that are either long-lived with few such drops, or short-lived
+
+# Examples
+
+```rust
+use send_cells::StickyCell;
+use std::rc::Rc;
+
+let cell = StickyCell::new(Rc::new(42));
+assert_eq!(cell.with(|rc| **rc), 42);
+
+// Send + Sync, even though Rc is neither
+fn assert_send_sync<T: Send + Sync>(_: &T) {}
+assert_send_sync(&cell);
+
+// Dropping on another thread does not panic
+std::thread::spawn(move || drop(cell)).join().unwrap();
+```
+*/
+
+use crate::send_cell::WrongThreadError;
+use crate::violation::{Operation, ViolationReport};
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::marker::PhantomData;
+use std::num::NonZeroU64;
+use std::panic::Location;
+#[cfg(feature = "backtrace")]
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_KEY: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// Values owned by the current thread, dropped with the thread.
+    static REGISTRY: RefCell<HashMap<u64, Box<dyn Any>>> = RefCell::new(HashMap::new());
+}
+
+/// A `Send + Sync` handle to a value stored in its origin thread's local storage.
+///
+/// See the [module documentation](crate::sticky_cell) for the drop behavior.
+///
+/// # Examples
+///
+/// ```rust
+/// use send_cells::StickyCell;
+/// use std::cell::RefCell;
+///
+/// let mut cell = StickyCell::new(RefCell::new(vec![1]));
+/// cell.with(|v| v.borrow_mut().push(2));
+/// cell.with_mut(|v| v.get_mut().push(3));
+/// assert_eq!(cell.into_inner().into_inner(), vec![1, 2, 3]);
+/// ```
+pub struct StickyCell<T: 'static> {
+    key: u64,
+    /// See [`crate::sys::current_thread_token`].
+    token: NonZeroU64,
+    created_at: &'static Location<'static>,
+    #[cfg(feature = "backtrace")]
+    backtrace: Arc<std::backtrace::Backtrace>,
+    _marker: PhantomData<*mut T>,
+}
+
+//The value itself stays on its origin thread; the handle only carries a key, and every
+//access checks the thread first.
+unsafe impl<T: 'static> Send for StickyCell<T> {}
+unsafe impl<T: 'static> Sync for StickyCell<T> {}
+
+impl<T: 'static> StickyCell<T> {
+    /// Moves `value` into the current thread's registry and returns a handle to it.
+    #[track_caller]
+    pub fn new(value: T) -> Self {
+        let key = NEXT_KEY.fetch_add(1, Ordering::Relaxed);
+        REGISTRY.with(|registry| registry.borrow_mut().insert(key, Box::new(value)));
+        StickyCell {
+            key,
+            token: crate::sys::current_thread_token(),
+            created_at: Location::caller(),
+            #[cfg(feature = "backtrace")]
+            backtrace: Arc::new(std::backtrace::Backtrace::capture()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns `true` if the current thread is the one that created the cell.
+    pub fn is_valid(&self) -> bool {
+        self.check().is_ok()
+    }
+
+    fn check(&self) -> Result<(), WrongThreadError> {
+        if self.token == crate::sys::current_thread_token() {
+            Ok(())
+        } else {
+            let current = crate::sys::thread::current().id();
+            let owner = crate::sys::thread_for_token(self.token).map(|thread| thread.id());
+            Err(WrongThreadError::new(owner, current))
+        }
+    }
+
+    /// Reports a wrong-thread access through the violation hook and the process-wide
+    /// policy.
+    ///
+    /// Access can never continue on the wrong thread, so the caller panics if this returns.
+    #[cold]
+    fn violation(
+        &self,
+        operation: Operation,
+        location: &'static Location<'static>,
+    ) -> ViolationReport {
+        let report = ViolationReport::new(
+            operation,
+            std::any::type_name::<T>(),
+            crate::sys::thread_for_token(self.token),
+            crate::sys::thread::current(),
+            self.created_at,
+            Some(location),
+            #[cfg(feature = "backtrace")]
+            self.backtrace.clone(),
+        )
+        .with_wrapper("StickyCell");
+        crate::violation::handle(None, &report);
+        report
+    }
+
+    /// Returns a pointer to the value, for shared access.  Must only be called on the origin
+    /// thread.
+    ///
+    /// The value is boxed, so the pointer stays valid while other cells are added to or
+    /// removed from the registry. It is only invalidated by dropping this cell or by the
+    /// thread exiting, neither of which can happen while `self` is borrowed on this thread.
+    ///
+    /// It is derived through shared references only, so that a nested `with` on the same
+    /// cell does not invalidate a reference handed out by the outer one.
+    fn value_ptr(&self) -> *const T {
+        REGISTRY
+            .try_with(|registry| {
+                registry
+                    .borrow()
+                    .get(&self.key)
+                    .and_then(|value| value.downcast_ref::<T>())
+                    .map(|value| value as *const T)
+            })
+            .ok()
+            .flatten()
+            .expect("StickyCell value was already destroyed with its thread")
+    }
+
+    /// Returns a pointer to the value, for exclusive access.  See [`Self::value_ptr`].
+    fn value_ptr_mut(&mut self) -> *mut T {
+        REGISTRY
+            .try_with(|registry| {
+                registry
+                    .borrow_mut()
+                    .get_mut(&self.key)
+                    .and_then(|value| value.downcast_mut::<T>())
+                    .map(|value| value as *mut T)
+            })
+            .ok()
+            .flatten()
+            .expect("StickyCell value was already destroyed with its thread")
+    }
+
+    /// Calls `f` with a shared reference to the value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from a thread other than the one that created the cell.
+    #[track_caller]
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        match self.try_with(f) {
+            Ok(r) => r,
+            Err(_) => {
+                let report = self.violation(Operation::Get, Location::caller());
+                panic!("{report}")
+            }
+        }
+    }
+
+    /// Calls `f` with a mutable reference to the value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from a thread other than the one that created the cell.
+    #[track_caller]
+    pub fn with_mut<R>(&mut self, f: impl FnOnce(&mut T) -> R) -> R {
+        match self.try_with_mut(f) {
+            Ok(r) => r,
+            Err(_) => {
+                let report = self.violation(Operation::GetMut, Location::caller());
+                panic!("{report}")
+            }
+        }
+    }
+
+    /// Calls `f` with a shared reference to the value, or returns an error if called from
+    /// the wrong thread.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::StickyCell;
+    /// use std::sync::Arc;
+    ///
+    /// let cell = Arc::new(StickyCell::new(5));
+    /// assert_eq!(cell.try_with(|v| *v), Ok(5));
+    ///
+    /// let other = cell.clone();
+    /// std::thread::spawn(move || assert!(other.try_with(|v| *v).is_err()))
+    ///     .join()
+    ///     .unwrap();
+    /// ```
+    pub fn try_with<R>(&self, f: impl FnOnce(&T) -> R) -> Result<R, WrongThreadError> {
+        self.check()?;
+        //safe because we are on the origin thread and only hand out a shared reference
+        Ok(f(unsafe { &*self.value_ptr() }))
+    }
+
+    /// Calls `f` with a mutable reference to the value, or returns an error if called from
+    /// the wrong thread.
+    pub fn try_with_mut<R>(&mut self, f: impl FnOnce(&mut T) -> R) -> Result<R, WrongThreadError> {
+        self.check()?;
+        //safe because we are on the origin thread and `&mut self` guarantees exclusivity
+        Ok(f(unsafe { &mut *self.value_ptr_mut() }))
+    }
+
+    /// Removes the value from the registry and returns it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from a thread other than the one that created the cell.
+    #[track_caller]
+    pub fn into_inner(self) -> T {
+        match self.try_into_inner() {
+            Ok(value) => value,
+            Err(cell) => {
+                let report = cell.violation(Operation::IntoInner, Location::caller());
+                panic!("{report}")
+            }
+        }
+    }
+
+    /// Removes the value from the registry and returns it, or returns the cell if called
+    /// from the wrong thread.
+    pub fn try_into_inner(self) -> Result<T, StickyCell<T>> {
+        if self.check().is_err() {
+            return Err(self);
+        }
+        let value = REGISTRY
+            .try_with(|registry| registry.borrow_mut().remove(&self.key))
+            .ok()
+            .flatten()
+            .expect("StickyCell value was already destroyed with its thread");
+        std::mem::forget(self);
+        Ok(*value.downcast::<T>().expect("StickyCell type mismatch"))
+    }
+}
+
+impl<T: 'static> Drop for StickyCell<T> {
+    fn drop(&mut self) {
+        if self.check().is_err() {
+            //the value is dropped with its thread
+            return;
+        }
+        //if the registry is already gone, the value was dropped with it
+        let value = REGISTRY
+            .try_with(|registry| registry.borrow_mut().remove(&self.key))
+            .ok()
+            .flatten();
+        //dropped outside the borrow, since the value's Drop may use other cells
+        drop(value);
+    }
+}
+
+impl<T: Debug + 'static> Debug for StickyCell<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut s = f.debug_struct("StickyCell");
+        let thread = crate::sys::thread_for_token(self.token).map(|thread| thread.id());
+        s.field("thread", &thread);
+        match self.check() {
+            Ok(()) => self.with(|value| s.field("value", value).finish()),
+            Err(_) => s.finish_non_exhaustive(),
+        }
+    }
+}
+
+impl<T: Default + 'static> Default for StickyCell<T> {
+    #[track_caller]
+    fn default() -> Self {
+        StickyCell::new(T::default())
+    }
+}
+
+impl<T: 'static> From<T> for StickyCell<T> {
+    #[track_caller]
+    fn from(value: T) -> Self {
+        StickyCell::new(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    struct DropFlag(Rc<std::cell::Cell<bool>>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_drop_on_origin_thread() {
+        let dropped = Rc::new(std::cell::Cell::new(false));
+        let cell = StickyCell::new(DropFlag(dropped.clone()));
+        assert!(!dropped.get());
+        drop(cell);
+        assert!(dropped.get());
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_nested_cells() {
+        let outer = StickyCell::new(StickyCell::new(Rc::new(3)));
+        let value = outer.with(|inner| {
+            //creating cells while another is borrowed must not conflict
+            let extra = StickyCell::new(1);
+            inner.with(|rc| **rc) + extra.into_inner()
+        });
+        assert_eq!(value, 4);
+        //a nested borrow of the same cell only creates shared references
+        let same = outer.with(|a| outer.with(|b| a.with(|x| **x) + b.with(|y| **y)));
+        assert_eq!(same, 6);
+    }
+
+    #[test]
+    fn test_drop_on_other_thread_defers_to_thread_exit() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        let (tx, rx) = std::sync::mpsc::channel();
+        let (dropped_tx, dropped_rx) = std::sync::mpsc::channel();
+        let owner = std::thread::spawn(move || {
+            struct SignalOnDrop(std::sync::mpsc::Sender<()>, PhantomData<Rc<()>>);
+            impl Drop for SignalOnDrop {
+                fn drop(&mut self) {
+                    self.0.send(()).unwrap();
+                }
+            }
+            tx.send(StickyCell::new(SignalOnDrop(dropped_tx, PhantomData)))
+                .unwrap();
+        });
+        let cell = rx.recv().unwrap();
+        assert!(!cell.is_valid());
+        drop(cell);
+        owner.join().unwrap();
+        //the value was dropped when its thread exited
+        dropped_rx.try_recv().unwrap();
+    }
+
+    #[test]
+    fn test_wrong_thread_access_panics() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        let cell = StickyCell::new(Rc::new(1));
+        let result = std::thread::spawn(move || {
+            let result =
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| cell.with(|rc| **rc)));
+            let message = *result.unwrap_err().downcast::<String>().unwrap();
+            (message, cell)
+        })
+        .join()
+        .unwrap();
+        //reported like any other violation
+        assert!(
+            result
+                .0
+                .starts_with("Access StickyCell from incorrect thread")
+        );
+        assert!(result.0.contains("alloc::rc::Rc<i32>"));
+        assert_eq!(result.1.into_inner(), Rc::new(1));
+    }
+}
@@ -16,11 +16,15 @@ mutex management.
 
 # Thread Safety Model
 
-[`SyncCell<T>`] uses a [`std::sync::Mutex`] internally to provide thread-safe access:
+[`SyncCell<T>`] uses an internal mutex to provide thread-safe access:
 - All access is through closures that receive references to the wrapped value
 - Mutex guards are automatically acquired and released by the closure methods
 - This prevents holding guards across await points or other blocking operations
 - The wrapped value itself doesn't need to implement `Sync`
+- [`SyncCell::try_with`] and [`SyncCell::with_timeout`] (and their `_mut` counterparts)
+  give up instead of blocking indefinitely
+- [`SyncCell::wait_until`] releases the lock until the cell is notified, like a
+  [`std::sync::Condvar`] built into the cell
 
 # Examples
 
@@ -70,9 +74,21 @@ cell.with(|map| {
 });
 ```
 
+# Poisoning
+
+As with [`std::sync::Mutex`], a panic inside a closure poisons the cell, and later calls to
+[`SyncCell::with`] and [`SyncCell::with_mut`] panic. The cell can be recovered:
+- [`SyncCell::is_poisoned`] and [`SyncCell::clear_poison`] inspect and reset the state
+- [`SyncCell::with_ignore_poison`] and [`SyncCell::with_mut_ignore_poison`] access the value
+  regardless
+- [`SyncCell::try_with`] and friends return [`TryWithError::Poisoned`] with the closure's
+  result instead of panicking
+- [`SyncCell::new_without_poisoning`] creates a cell that is never poisoned
+
 # Avoiding Deadlocks
 
-The closure-based API automatically prevents common deadlock scenarios:
+The closure-based API automatically prevents common deadlock scenarios. Guards cannot be
+held across `.await` points or leaked past the closure:
 
 ```rust
 use send_cells::SyncCell;
@@ -88,12 +104,31 @@ cell.with_mut(|vec| {
     vec.push(4);
 }); // No deadlock - previous guard was released
 ```
+
+The one remaining way to deadlock on a single cell is to access it again from inside one
+of its own closures. `std::sync::Mutex` would block forever; `SyncCell` tracks the thread
+holding the lock and panics instead (or, for [`SyncCell::try_with`] and friends, returns
+[`TryWithError::Reentrant`]). Since the outer closure is unwound by that panic, the cell is
+poisoned. For nested shared access on one thread, use
+[`crate::reentrant_sync_cell::ReentrantSyncCell`].
+
+```rust
+use send_cells::SyncCell;
+use send_cells::sync_cell::TryWithError;
+
+let cell = SyncCell::new(1);
+let nested = cell.with(|_| cell.try_with_mut(|v| *v += 1));
+assert_eq!(nested, Err(TryWithError::Reentrant));
+```
 */
 
+use crate::lock::{LockError, LockGuard, RawLock};
+use crate::send_cell::SendCell;
 use crate::unsafe_sync_cell::UnsafeSyncCell;
-use std::fmt::{Debug, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 use std::hash::{Hash, Hasher};
-use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::time::Duration;
 
 /// A runtime-checked cell that allows sharing non-Sync types between threads.
 ///
@@ -153,7 +188,7 @@ use std::sync::Mutex;
 /// Access is always protected by the internal mutex, ensuring thread safety.
 pub struct SyncCell<T> {
     inner: UnsafeSyncCell<T>,
-    mutex: Mutex<()>,
+    lock: RawLock,
 }
 
 impl<T> SyncCell<T> {
@@ -162,6 +197,9 @@ impl<T> SyncCell<T> {
     /// The value will be protected by an internal mutex, allowing safe shared
     /// access from multiple threads through the closure-based access methods.
     ///
+    /// This is a `const fn`, so a `SyncCell` can be placed directly in a `static`. For values
+    /// that cannot be built in a `const` context, see [`crate::LazySyncCell`].
+    ///
     /// # Examples
     ///
     /// ```rust
@@ -175,14 +213,86 @@ impl<T> SyncCell<T> {
     ///     println!("{}", rc);
     /// });
     /// ```
+    ///
+    /// In a `static`:
+    ///
+    /// ```rust
+    /// use send_cells::SyncCell;
+    ///
+    /// static COUNTER: SyncCell<u64> = SyncCell::new(0);
+    ///
+    /// COUNTER.update(|n| n + 1);
+    /// assert_eq!(COUNTER.get_copy(), 1);
+    /// ```
     #[inline]
-    pub fn new(value: T) -> SyncCell<T> {
+    #[track_caller]
+    pub const fn new(value: T) -> SyncCell<T> {
         SyncCell {
             inner: UnsafeSyncCell::new(value),
-            mutex: Mutex::new(()),
+            lock: RawLock::new(true),
         }
     }
 
+    /// Creates a new `SyncCell` that is never poisoned.
+    ///
+    /// A panic inside a closure passed to this cell leaves the lock usable, as though the
+    /// closure had returned. Use this when the wrapped value stays consistent even if an
+    /// update is interrupted, or when availability matters more than detecting the panic.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SyncCell;
+    /// use std::panic::AssertUnwindSafe;
+    ///
+    /// let cell = SyncCell::new_without_poisoning(0);
+    /// let _ = std::panic::catch_unwind(AssertUnwindSafe(|| cell.with_mut(|_| panic!("bug"))));
+    /// assert!(!cell.is_poisoned());
+    /// assert_eq!(cell.with(|v| *v), 0);
+    /// ```
+    #[inline]
+    #[track_caller]
+    pub const fn new_without_poisoning(value: T) -> SyncCell<T> {
+        SyncCell {
+            inner: UnsafeSyncCell::new(value),
+            lock: RawLock::new(false),
+        }
+    }
+
+    /// Returns `true` if a closure panicked while holding the lock.
+    ///
+    /// While the cell is poisoned, [`Self::with`] and [`Self::with_mut`] panic. Use
+    /// [`Self::clear_poison`] to recover, or [`Self::with_ignore_poison`] to access the
+    /// value regardless.
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.lock.is_poisoned()
+    }
+
+    /// Clears the poisoned state, making the cell usable again.
+    ///
+    /// Call this once the value has been checked or repaired (for example, inside
+    /// [`Self::with_mut_ignore_poison`]).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SyncCell;
+    /// use std::panic::AssertUnwindSafe;
+    ///
+    /// let cell = SyncCell::new(vec![1, 2, 3]);
+    /// let _ = std::panic::catch_unwind(AssertUnwindSafe(|| cell.with_mut(|_| panic!("bug"))));
+    /// assert!(cell.is_poisoned());
+    ///
+    /// cell.with_mut_ignore_poison(|v| v.clear());
+    /// cell.clear_poison();
+    /// assert_eq!(cell.with(|v| v.len()), 0);
+    /// ```
+    #[inline]
+    pub fn clear_poison(&self) {
+        self.lock.clear_poison()
+    }
+
     /// Accesses the underlying value through a synchronous closure.
     ///
     /// The closure receives a shared reference to the wrapped value and must
@@ -195,7 +305,8 @@ impl<T> SyncCell<T> {
     /// # Panics
     ///
     /// Panics if the mutex is poisoned (i.e., another thread panicked while
-    /// holding the lock).
+    /// holding the lock), or if called from inside another closure on this cell on the
+    /// same thread, which would otherwise deadlock.
     ///
     /// # Examples
     ///
@@ -215,7 +326,7 @@ impl<T> SyncCell<T> {
     /// ```
     #[inline]
     pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
-        let _guard = self.mutex.lock().unwrap();
+        let _guard = unpoisoned(acquired(self.lock.lock()));
         let value = unsafe { self.inner.get() };
         f(value)
     }
@@ -232,7 +343,8 @@ impl<T> SyncCell<T> {
     /// # Panics
     ///
     /// Panics if the mutex is poisoned (i.e., another thread panicked while
-    /// holding the lock).
+    /// holding the lock), or if called from inside another closure on this cell on the
+    /// same thread, which would otherwise deadlock.
     ///
     /// # Examples
     ///
@@ -253,12 +365,304 @@ impl<T> SyncCell<T> {
     /// ```
     #[inline]
     pub fn with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
-        let _guard = self.mutex.lock().unwrap();
+        let _guard = unpoisoned(acquired(self.lock.lock()));
         //safe since we hold the lock
         let value = unsafe { self.inner.get_mut_unchecked() };
         f(value)
     }
 
+    /// Accesses the underlying value through a closure, even if the mutex is poisoned.
+    ///
+    /// This blocks like [`Self::with`], but does not check for poisoning. The poisoned state
+    /// is left unchanged.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from inside another closure on this cell on the same thread.
+    #[inline]
+    pub fn with_ignore_poison<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        let _guard = acquired(self.lock.lock());
+        let value = unsafe { self.inner.get() };
+        f(value)
+    }
+
+    /// Accesses the underlying value mutably through a closure, even if the mutex is
+    /// poisoned.
+    ///
+    /// This blocks like [`Self::with_mut`], but does not check for poisoning. The poisoned
+    /// state is left unchanged; call [`Self::clear_poison`] after repairing the value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from inside another closure on this cell on the same thread.
+    #[inline]
+    pub fn with_mut_ignore_poison<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let _guard = acquired(self.lock.lock());
+        //safe since we hold the lock
+        let value = unsafe { self.inner.get_mut_unchecked() };
+        f(value)
+    }
+
+    /// Accesses the underlying value through a closure, if the lock is free.
+    ///
+    /// Unlike [`Self::with`], this never blocks: if another thread holds the lock, it
+    /// returns [`TryWithError::WouldBlock`] immediately without calling `f`. If the current
+    /// thread holds the lock, it returns [`TryWithError::Reentrant`].
+    ///
+    /// If the mutex is poisoned, `f` still runs, and its result is returned inside
+    /// [`TryWithError::Poisoned`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SyncCell;
+    /// use send_cells::sync_cell::TryWithError;
+    ///
+    /// let cell = SyncCell::new(42);
+    /// assert_eq!(cell.try_with(|v| *v), Ok(42));
+    ///
+    /// // The lock is held by the outer closure, on this thread
+    /// let nested = cell.with(|_| cell.try_with(|v| *v));
+    /// assert_eq!(nested, Err(TryWithError::Reentrant));
+    /// ```
+    #[inline]
+    pub fn try_with<R>(&self, f: impl FnOnce(&T) -> R) -> Result<R, TryWithError<R>> {
+        let guard = self.lock.try_lock()?;
+        let value = unsafe { self.inner.get() };
+        poison_checked(&guard, f(value))
+    }
+
+    /// Accesses the underlying value mutably through a closure, if the lock is free.
+    ///
+    /// Unlike [`Self::with_mut`], this never blocks: if another thread holds the lock, it
+    /// returns [`TryWithError::WouldBlock`] immediately without calling `f`.
+    ///
+    /// If the mutex is poisoned, `f` still runs, and its result is returned inside
+    /// [`TryWithError::Poisoned`].
+    #[inline]
+    pub fn try_with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, TryWithError<R>> {
+        let guard = self.lock.try_lock()?;
+        //safe since we hold the lock
+        let value = unsafe { self.inner.get_mut_unchecked() };
+        poison_checked(&guard, f(value))
+    }
+
+    /// Accesses the underlying value through a closure, waiting at most `timeout` for
+    /// the lock.
+    ///
+    /// Returns [`TryWithError::TimedOut`] without calling `f` if the lock could not be
+    /// acquired in time.
+    ///
+    /// If the mutex is poisoned, `f` still runs, and its result is returned inside
+    /// [`TryWithError::Poisoned`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SyncCell;
+    /// use std::time::Duration;
+    ///
+    /// let cell = SyncCell::new(vec![1, 2, 3]);
+    /// let len = cell.with_timeout(Duration::from_millis(10), |v| v.len());
+    /// assert_eq!(len, Ok(3));
+    /// ```
+    #[inline]
+    pub fn with_timeout<R>(
+        &self,
+        timeout: Duration,
+        f: impl FnOnce(&T) -> R,
+    ) -> Result<R, TryWithError<R>> {
+        let guard = self.lock.lock_timeout(timeout)?;
+        let value = unsafe { self.inner.get() };
+        poison_checked(&guard, f(value))
+    }
+
+    /// Accesses the underlying value mutably through a closure, waiting at most `timeout`
+    /// for the lock.
+    ///
+    /// Returns [`TryWithError::TimedOut`] without calling `f` if the lock could not be
+    /// acquired in time.
+    ///
+    /// If the mutex is poisoned, `f` still runs, and its result is returned inside
+    /// [`TryWithError::Poisoned`].
+    #[inline]
+    pub fn with_mut_timeout<R>(
+        &self,
+        timeout: Duration,
+        f: impl FnOnce(&mut T) -> R,
+    ) -> Result<R, TryWithError<R>> {
+        let guard = self.lock.lock_timeout(timeout)?;
+        //safe since we hold the lock
+        let value = unsafe { self.inner.get_mut_unchecked() };
+        poison_checked(&guard, f(value))
+    }
+
+    /// Accesses the underlying value through a closure, waiting for the lock asynchronously.
+    ///
+    /// The returned future does not block the thread while the lock is contended. Instead, it
+    /// joins a first-in, first-out queue of waiting tasks and is woken when it reaches the front
+    /// and the lock is free. The closure itself still runs synchronously, so the lock can never
+    /// be held across an `.await`. This works with any executor, including single-threaded
+    /// ones on wasm32, where blocking the main thread is not allowed.
+    ///
+    /// Dropping the future before it completes gives up its place in the queue.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the mutex is poisoned (i.e., another thread panicked while
+    /// holding the lock), or if called from inside another closure on this cell on the
+    /// same thread, which would otherwise deadlock.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SyncCell;
+    /// use std::sync::Arc;
+    ///
+    /// async fn total(cell: Arc<SyncCell<Vec<i32>>>) -> i32 {
+    ///     cell.with_mut_async(|v| v.push(3)).await;
+    ///     cell.with_async(|v| v.iter().sum()).await
+    /// }
+    ///
+    /// # let waker = std::task::Waker::noop();
+    /// # let mut cx = std::task::Context::from_waker(&waker);
+    /// let future = std::pin::pin!(total(Arc::new(SyncCell::new(vec![1, 2]))));
+    /// # let std::task::Poll::Ready(sum) = future.poll(&mut cx) else { unreachable!() };
+    /// # assert_eq!(sum, 6);
+    /// ```
+    pub async fn with_async<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        let _guard = unpoisoned(acquired(self.lock.lock_async().await));
+        let value = unsafe { self.inner.get() };
+        f(value)
+    }
+
+    /// Accesses the underlying value mutably through a closure, waiting for the lock
+    /// asynchronously.
+    ///
+    /// See [`Self::with_async`] for how waiting works.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the mutex is poisoned (i.e., another thread panicked while
+    /// holding the lock), or if called from inside another closure on this cell on the
+    /// same thread, which would otherwise deadlock.
+    pub async fn with_mut_async<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let _guard = unpoisoned(acquired(self.lock.lock_async().await));
+        //safe since we hold the lock
+        let value = unsafe { self.inner.get_mut_unchecked() };
+        f(value)
+    }
+
+    /// Blocks until `f` returns `Some`, then returns its result.
+    ///
+    /// `f` is called with the lock held: first immediately, then again each time the cell is
+    /// notified through [`Self::notify_one`] or [`Self::notify_all`]. Between calls the lock is
+    /// released, so other threads can change the value, like [`std::sync::Condvar::wait_while`].
+    /// Since `f` receives the value mutably, it can both check a condition and act on it, for
+    /// example by popping an item once a queue is non-empty.
+    ///
+    /// Whoever changes the value in a way `f` may be waiting for must notify the cell.
+    /// Spurious wakeups are possible, so `f` should not assume anything changed.
+    ///
+    /// This blocks the thread; it is not suitable for async executors.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cell is poisoned (also when it becomes poisoned while waiting), or if
+    /// called from inside a closure on this cell.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SyncCell;
+    /// use std::collections::VecDeque;
+    /// use std::sync::Arc;
+    /// use std::thread;
+    ///
+    /// let queue = Arc::new(SyncCell::new(VecDeque::new()));
+    ///
+    /// let producer = {
+    ///     let queue = Arc::clone(&queue);
+    ///     thread::spawn(move || {
+    ///         queue.with_mut(|q| q.push_back("job"));
+    ///         queue.notify_one();
+    ///     })
+    /// };
+    ///
+    /// assert_eq!(queue.wait_until(|q| q.pop_front()), "job");
+    /// producer.join().unwrap();
+    /// ```
+    pub fn wait_until<R>(&self, mut f: impl FnMut(&mut T) -> Option<R>) -> R {
+        let mut guard = unpoisoned(acquired(self.lock.lock()));
+        loop {
+            //safe since we hold the lock
+            let value = unsafe { self.inner.get_mut_unchecked() };
+            if let Some(r) = f(value) {
+                return r;
+            }
+            guard.wait(None);
+            guard = unpoisoned(guard);
+        }
+    }
+
+    /// Like [`Self::wait_until`], but gives up after `timeout`.
+    ///
+    /// Returns `None` if `f` still returned `None` when the timeout elapsed. `f` is called one
+    /// last time at that point. Waiting for the lock itself is not bounded by the timeout,
+    /// just as [`Self::with_mut`] is not.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cell is poisoned (also when it becomes poisoned while waiting), or if
+    /// called from inside a closure on this cell.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SyncCell;
+    /// use std::time::Duration;
+    ///
+    /// let ready = SyncCell::new(false);
+    /// let result = ready.wait_until_timeout(Duration::from_millis(10), |r| r.then_some(()));
+    /// assert_eq!(result, None);
+    /// ```
+    pub fn wait_until_timeout<R>(
+        &self,
+        timeout: Duration,
+        mut f: impl FnMut(&mut T) -> Option<R>,
+    ) -> Option<R> {
+        //too far in the future to represent means no deadline
+        let deadline = std::time::Instant::now().checked_add(timeout);
+        let mut guard = unpoisoned(acquired(self.lock.lock()));
+        let mut timed_out = false;
+        loop {
+            //safe since we hold the lock
+            let value = unsafe { self.inner.get_mut_unchecked() };
+            if let Some(r) = f(value) {
+                return Some(r);
+            }
+            if timed_out {
+                return None;
+            }
+            timed_out = !guard.wait(deadline);
+            guard = unpoisoned(guard);
+        }
+    }
+
+    /// Wakes one thread blocked in [`Self::wait_until`] or [`Self::wait_until_timeout`].
+    ///
+    /// Call this after changing the value, either inside or after the closure that changed it.
+    #[inline]
+    pub fn notify_one(&self) {
+        self.lock.notify(false)
+    }
+
+    /// Wakes every thread blocked in [`Self::wait_until`] or [`Self::wait_until_timeout`].
+    #[inline]
+    pub fn notify_all(&self) {
+        self.lock.notify(true)
+    }
+
     /// Consumes the cell and returns the wrapped value.
     ///
     /// This method takes ownership of the `SyncCell` and returns the wrapped value
@@ -281,6 +685,26 @@ impl<T> SyncCell<T> {
         self.inner.into_inner()
     }
 
+    /// Returns a mutable reference to the wrapped value, without locking.
+    ///
+    /// Since this borrows the cell mutably, no closure can be running on it, so no lock is
+    /// needed. Like [`Self::into_inner`], this does not check for poisoning; use
+    /// [`Self::is_poisoned`] if that matters.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SyncCell;
+    ///
+    /// let mut cell = SyncCell::new(vec![1]);
+    /// cell.get_mut().push(2);
+    /// assert_eq!(cell.with(|v| v.len()), 2);
+    /// ```
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+
     /// Unsafely accesses the underlying value without acquiring the mutex.
     ///
     /// # Safety
@@ -349,6 +773,365 @@ impl<T> SyncCell<T> {
     }
 }
 
+// ===========================================================================================
+// VALUE-LEVEL HELPERS
+// ===========================================================================================
+// Shorthands for the most common closures, named after their `std::cell::Cell` counterparts.
+// Like `with_mut`, they panic if the cell is poisoned or accessed reentrantly. Values that
+// are replaced are dropped after the lock is released, so their `Drop` may use the cell.
+
+impl<T> SyncCell<T> {
+    /// Replaces the wrapped value, returning the old one.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SyncCell;
+    ///
+    /// let cell = SyncCell::new(String::from("old"));
+    /// assert_eq!(cell.replace(String::from("new")), "old");
+    /// assert_eq!(cell.into_inner(), "new");
+    /// ```
+    #[inline]
+    pub fn replace(&self, value: T) -> T {
+        self.with_mut(|v| std::mem::replace(v, value))
+    }
+
+    /// Sets the wrapped value, dropping the old one.
+    #[inline]
+    pub fn set(&self, value: T) {
+        drop(self.replace(value));
+    }
+
+    /// Replaces the wrapped value with the result of `f`, which receives the current value.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SyncCell;
+    ///
+    /// let cell = SyncCell::new(vec![1, 2]);
+    /// cell.update(|v| v.iter().map(|x| x * 10).collect());
+    /// assert_eq!(cell.into_inner(), vec![10, 20]);
+    /// ```
+    #[inline]
+    pub fn update(&self, f: impl FnOnce(&T) -> T) {
+        let old = self.with_mut(|v| {
+            let new = f(v);
+            std::mem::replace(v, new)
+        });
+        drop(old);
+    }
+
+    /// Swaps the values of two cells.
+    ///
+    /// Both cells are locked in the same order as [`with_all`], so two threads swapping the
+    /// same pair of cells in opposite directions cannot deadlock. Swapping a cell with itself
+    /// does nothing.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SyncCell;
+    ///
+    /// let a = SyncCell::new(1);
+    /// let b = SyncCell::new(2);
+    /// a.swap(&b);
+    /// assert_eq!((a.into_inner(), b.into_inner()), (2, 1));
+    /// ```
+    pub fn swap(&self, other: &SyncCell<T>) {
+        if std::ptr::eq(self, other) {
+            return;
+        }
+        with_all((self, other), |(a, b)| std::mem::swap(a, b));
+    }
+}
+
+impl<T: Default> SyncCell<T> {
+    /// Takes the wrapped value, leaving `T::default()` in its place.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SyncCell;
+    ///
+    /// let cell = SyncCell::new(vec!["queued"]);
+    /// assert_eq!(cell.take(), vec!["queued"]);
+    /// assert!(cell.with(|v| v.is_empty()));
+    /// ```
+    #[inline]
+    pub fn take(&self) -> T {
+        self.replace(T::default())
+    }
+}
+
+impl<T: Clone> SyncCell<T> {
+    /// Returns a clone of the wrapped value.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SyncCell;
+    ///
+    /// let cell = SyncCell::new(String::from("config"));
+    /// assert_eq!(cell.get_cloned(), "config");
+    /// ```
+    #[inline]
+    pub fn get_cloned(&self) -> T {
+        self.with(T::clone)
+    }
+}
+
+impl<T: Copy> SyncCell<T> {
+    /// Returns a copy of the wrapped value.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::SyncCell;
+    ///
+    /// let cell = SyncCell::new(7);
+    /// assert_eq!(cell.get_copy(), 7);
+    /// ```
+    #[inline]
+    pub fn get_copy(&self) -> T {
+        self.with(|v| *v)
+    }
+}
+
+/// Panics if the current thread already held the lock, which would otherwise deadlock.
+#[inline]
+fn acquired(result: Result<LockGuard<'_>, LockError>) -> LockGuard<'_> {
+    match result {
+        Ok(guard) => guard,
+        Err(_) => reentrant(),
+    }
+}
+
+#[cold]
+fn reentrant() -> ! {
+    panic!(
+        "SyncCell accessed reentrantly: this thread already holds the lock, so waiting for it would deadlock"
+    )
+}
+
+/// Panics if the lock was poisoned, matching `Mutex::lock().unwrap()`.
+#[inline]
+fn unpoisoned(guard: LockGuard<'_>) -> LockGuard<'_> {
+    if guard.is_poisoned() {
+        poisoned();
+    }
+    guard
+}
+
+#[cold]
+fn poisoned() -> ! {
+    panic!("SyncCell is poisoned: a thread panicked while holding the lock")
+}
+
+/// Wraps `f`'s result in [`TryWithError::Poisoned`] if the lock was poisoned.
+#[inline]
+fn poison_checked<R>(guard: &LockGuard<'_>, result: R) -> Result<R, TryWithError<R>> {
+    if guard.is_poisoned() {
+        Err(TryWithError::Poisoned(PoisonError::new(result)))
+    } else {
+        Ok(result)
+    }
+}
+
+/// The error returned by [`SyncCell::try_with`], [`SyncCell::with_timeout`], and their
+/// `_mut` counterparts.
+///
+/// `R` is the closure's result type. When the mutex is poisoned the closure still runs,
+/// and its result can be recovered from [`TryWithError::Poisoned`].
+///
+/// # Examples
+///
+/// ```rust
+/// use send_cells::{SyncCell, TryWithError};
+/// use std::panic::AssertUnwindSafe;
+///
+/// let cell = SyncCell::new(1);
+/// let _ = std::panic::catch_unwind(AssertUnwindSafe(|| cell.with(|_| panic!("bug"))));
+///
+/// match cell.try_with(|v| *v) {
+///     Err(TryWithError::Poisoned(e)) => assert_eq!(e.into_inner(), 1),
+///     other => panic!("unexpected: {other:?}"),
+/// }
+/// ```
+pub enum TryWithError<R> {
+    /// The lock is held elsewhere, and the operation does not block.
+    WouldBlock,
+    /// The lock was not released before the timeout elapsed.
+    TimedOut,
+    /// The current thread already holds the lock (the call is nested inside another closure
+    /// on the same cell), so waiting for it would deadlock.
+    Reentrant,
+    /// The mutex is poisoned; the closure ran anyway and its result is inside.
+    Poisoned(PoisonError<R>),
+}
+
+impl<R> Debug for TryWithError<R> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TryWithError::WouldBlock => f.write_str("WouldBlock"),
+            TryWithError::TimedOut => f.write_str("TimedOut"),
+            TryWithError::Reentrant => f.write_str("Reentrant"),
+            TryWithError::Poisoned(e) => f.debug_tuple("Poisoned").field(e).finish(),
+        }
+    }
+}
+
+impl<R> Display for TryWithError<R> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TryWithError::WouldBlock => f.write_str("SyncCell is locked"),
+            TryWithError::TimedOut => f.write_str("timed out waiting for SyncCell lock"),
+            TryWithError::Reentrant => {
+                f.write_str("SyncCell is already locked by the current thread")
+            }
+            TryWithError::Poisoned(_) => f.write_str("SyncCell is poisoned"),
+        }
+    }
+}
+
+impl<R> std::error::Error for TryWithError<R> {}
+
+impl<R: PartialEq> PartialEq for TryWithError<R> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (TryWithError::WouldBlock, TryWithError::WouldBlock) => true,
+            (TryWithError::TimedOut, TryWithError::TimedOut) => true,
+            (TryWithError::Reentrant, TryWithError::Reentrant) => true,
+            (TryWithError::Poisoned(a), TryWithError::Poisoned(b)) => a.get_ref() == b.get_ref(),
+            _ => false,
+        }
+    }
+}
+
+impl<R: Eq> Eq for TryWithError<R> {}
+
+impl<R> From<LockError> for TryWithError<R> {
+    fn from(e: LockError) -> Self {
+        match e {
+            LockError::WouldBlock => TryWithError::WouldBlock,
+            LockError::TimedOut => TryWithError::TimedOut,
+            LockError::Reentrant => TryWithError::Reentrant,
+        }
+    }
+}
+
+impl<R> From<PoisonError<R>> for TryWithError<R> {
+    fn from(e: PoisonError<R>) -> Self {
+        TryWithError::Poisoned(e)
+    }
+}
+
+/// Accesses several cells mutably at once, locking them in a consistent order.
+///
+/// `cells` is a tuple of two to six `&SyncCell`s, and `f` receives a tuple of mutable
+/// references to their values. All locks are held while `f` runs, so the update is atomic
+/// with respect to every other access to those cells.
+///
+/// The locks are always acquired in order of the cells' addresses, regardless of the order
+/// they are passed in, so concurrent callers naming the same cells in different orders
+/// cannot deadlock each other.
+///
+/// # Panics
+///
+/// Panics if the same cell appears more than once in `cells`, if any of the cells is
+/// poisoned, or if the current thread is already inside a closure on one of the cells.
+///
+/// # Examples
+///
+/// ```rust
+/// use send_cells::SyncCell;
+/// use send_cells::sync_cell::with_all;
+/// use std::collections::VecDeque;
+///
+/// let pending = SyncCell::new(VecDeque::from(["job"]));
+/// let running = SyncCell::new(Vec::new());
+///
+/// // Move an item between two queues atomically
+/// with_all((&pending, &running), |(pending, running)| {
+///     if let Some(job) = pending.pop_front() {
+///         running.push(job);
+///     }
+/// });
+///
+/// assert_eq!(running.with(|r| r.clone()), vec!["job"]);
+/// ```
+pub fn with_all<C: CellTuple, R>(cells: C, f: impl for<'a> FnOnce(Refs<'a, C>) -> R) -> R {
+    cells.with_all(f)
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// The mutable references that [`with_all`] hands out for the cells in `C`.
+pub type Refs<'a, C> = <C as CellRefs<'a>>::Refs;
+
+/// Names the references [`with_all`] hands out for a [`CellTuple`], for a given borrow.
+///
+/// This is an implementation detail of [`CellTuple`]: the `Implied` parameter only exists
+/// so that `for<'a> CellRefs<'a>` implies `Self: 'a`.
+pub trait CellRefs<'a, Implied = &'a Self>: sealed::Sealed {
+    /// The tuple of mutable references handed to the closure.
+    type Refs;
+}
+
+/// A tuple of `&SyncCell`s that can be locked together by [`with_all`].
+///
+/// Implemented for tuples of two to six cell references. This trait is sealed.
+pub trait CellTuple: for<'a> CellRefs<'a> {
+    /// Locks every cell and calls `f` with mutable references to their values.
+    ///
+    /// Equivalent to [`with_all`].
+    fn with_all<R>(self, f: impl for<'a> FnOnce(Refs<'a, Self>) -> R) -> R;
+}
+
+/// Acquires `locks` in address order, panicking if any lock appears twice.
+fn lock_in_order<const N: usize>(mut locks: [&RawLock; N]) -> [LockGuard<'_>; N] {
+    locks.sort_unstable_by_key(|lock| *lock as *const RawLock);
+    if locks.windows(2).any(|pair| std::ptr::eq(pair[0], pair[1])) {
+        panic!("with_all was passed the same SyncCell more than once");
+    }
+    let results = locks.map(RawLock::lock);
+    let reentered = results.iter().any(Result::is_err);
+    if reentered || results.iter().flatten().any(LockGuard::is_poisoned) {
+        //release every lock before panicking, so unwinding does not poison the other cells
+        drop(results);
+        if reentered { reentrant() } else { poisoned() }
+    }
+    results.map(|result| result.unwrap_or_else(|_| unreachable!()))
+}
+
+macro_rules! impl_cell_tuple {
+    ($($T:ident $idx:tt),+) => {
+        impl<'c, $($T),+> sealed::Sealed for ($(&'c SyncCell<$T>,)+) {}
+
+        impl<'a, 'c, $($T),+> CellRefs<'a, &'a Self> for ($(&'c SyncCell<$T>,)+) {
+            type Refs = ($(&'a mut $T,)+);
+        }
+
+        impl<'c, $($T),+> CellTuple for ($(&'c SyncCell<$T>,)+) {
+            fn with_all<R>(self, f: impl for<'a> FnOnce(Refs<'a, Self>) -> R) -> R {
+                let _guards = lock_in_order([$(&self.$idx.lock),+]);
+                //safe since we hold every lock, and the cells are distinct
+                let refs = ($(unsafe { self.$idx.inner.get_mut_unchecked() },)+);
+                f(refs)
+            }
+        }
+    };
+}
+
+impl_cell_tuple!(A 0, B 1);
+impl_cell_tuple!(A 0, B 1, C 2);
+impl_cell_tuple!(A 0, B 1, C 2, D 3);
+impl_cell_tuple!(A 0, B 1, C 2, D 3, E 4);
+impl_cell_tuple!(A 0, B 1, C 2, D 3, E 4, F 5);
+
 // SAFETY: SyncCell<T> can be Send when T: Send because the mutex ensures
 // that only one thread can access the inner value at a time.
 unsafe impl<T: Send> Send for SyncCell<T> {}
@@ -383,28 +1166,100 @@ impl<T: std::fmt::Display> std::fmt::Display for SyncCell<T> {
 }
 
 impl<T: Default> Default for SyncCell<T> {
+    #[track_caller]
     fn default() -> SyncCell<T> {
         SyncCell::new(T::default())
     }
 }
 
 impl<T> From<T> for SyncCell<T> {
+    #[track_caller]
     fn from(value: T) -> Self {
         SyncCell::new(value)
     }
 }
 
+// Exclusive access needs no lock, so AsMut is fine even though AsRef is not
+impl<T> AsMut<T> for SyncCell<T> {
+    fn as_mut(&mut self) -> &mut T {
+        self.get_mut()
+    }
+}
+
+// Conversions between the cell types move the value without copying or locking
+impl<T> From<UnsafeSyncCell<T>> for SyncCell<T> {
+    #[track_caller]
+    fn from(cell: UnsafeSyncCell<T>) -> Self {
+        SyncCell::new(cell.into_inner())
+    }
+}
+
+impl<T> From<SyncCell<T>> for UnsafeSyncCell<T> {
+    fn from(cell: SyncCell<T>) -> Self {
+        cell.inner
+    }
+}
+
+/// Unwraps the [`SendCell`], or gives it back if called from a thread other than the one
+/// where it was created, as [`SendCell::try_into_inner`] does.
+///
+/// # Examples
+///
+/// ```rust
+/// use send_cells::{SendCell, SyncCell};
+///
+/// let send_cell = SendCell::new(vec![1]);
+/// let send_cell = std::thread::spawn(move || {
+///     let result: Result<SyncCell<Vec<i32>>, _> = send_cell.try_into();
+///     result.unwrap_err()
+/// })
+/// .join()
+/// .unwrap();
+/// let cell: SyncCell<Vec<i32>> = send_cell.try_into().ok().unwrap();
+/// assert_eq!(cell.into_inner(), vec![1]);
+/// ```
+impl<T> TryFrom<SendCell<T>> for SyncCell<T> {
+    type Error = SendCell<T>;
+
+    #[track_caller]
+    fn try_from(cell: SendCell<T>) -> Result<Self, Self::Error> {
+        cell.try_into_inner().map(SyncCell::new)
+    }
+}
+
+/// Binds the value to the current thread.
+impl<T> From<SyncCell<T>> for SendCell<T> {
+    #[track_caller]
+    fn from(cell: SyncCell<T>) -> Self {
+        SendCell::new(cell.into_inner())
+    }
+}
+
 // Clone creates a new independent SyncCell with a cloned value
 impl<T: Clone> Clone for SyncCell<T> {
+    #[track_caller]
     fn clone(&self) -> Self {
-        self.with(|value| SyncCell::new(value.clone()))
+        SyncCell::new(self.with(T::clone))
     }
 }
 
 // Comparison traits - all use safe closure-based access
+/// Calls `f` with the values of two cells, locked in address order.
+///
+/// Nesting `with` calls instead would make the lock order depend on the argument order.
+/// A cell compared with itself is locked once, and its value compared with itself.
+fn with_pair<T, R>(a: &SyncCell<T>, b: &SyncCell<T>, f: impl FnOnce(&T, &T) -> R) -> R {
+    if std::ptr::eq(a, b) {
+        return a.with(|value| f(value, value));
+    }
+    let _guards = lock_in_order([&a.lock, &b.lock]);
+    //safe since we hold both locks
+    unsafe { f(a.inner.get(), b.inner.get()) }
+}
+
 impl<T: PartialEq> PartialEq for SyncCell<T> {
     fn eq(&self, other: &Self) -> bool {
-        self.with(|a| other.with(|b| a == b))
+        with_pair(self, other, |a, b| a == b)
     }
 }
 
@@ -412,13 +1267,13 @@ impl<T: Eq> Eq for SyncCell<T> {}
 
 impl<T: PartialOrd> PartialOrd for SyncCell<T> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.with(|a| other.with(|b| a.partial_cmp(b)))
+        with_pair(self, other, |a, b| a.partial_cmp(b))
     }
 }
 
 impl<T: Ord> Ord for SyncCell<T> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.with(|a| other.with(|b| a.cmp(b)))
+        with_pair(self, other, |a, b| a.cmp(b))
     }
 }
 
@@ -506,6 +1361,13 @@ mod tests {
 
         assert_eq!(cell1, cell2);
         assert_ne!(cell1, cell3);
+        //comparing a cell with itself must not lock it twice
+        assert!(cell1 == cell1);
+        assert!(!cell1.is_poisoned());
+        //the value's own comparison decides, even against itself
+        let nan = SyncCell::new(f64::NAN);
+        assert!(nan != nan);
+        assert_eq!(nan.partial_cmp(&nan), None);
     }
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
@@ -518,10 +1380,16 @@ mod tests {
         assert!(cell1 < cell2);
         assert!(cell2 < cell3);
         assert!(cell1 < cell3);
+        //either argument order locks in the same order
+        assert!(cell3 > cell1);
+        assert_eq!(cell2.cmp(&cell2), std::cmp::Ordering::Equal);
+        assert_eq!(cell2.partial_cmp(&cell2), Some(std::cmp::Ordering::Equal));
     }
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
     #[test]
+    //the lock has interior mutability, but Hash and Eq only look at the value
+    #[allow(clippy::mutable_key_type)]
     fn test_hash() {
         use std::collections::HashMap;
 
@@ -546,6 +1414,9 @@ mod tests {
         let cell = SyncCell::new(42);
         assert_send(&cell);
         assert_sync(&cell);
+        //the async accessors can be used from multithreaded executors
+        assert_send(&cell.with_async(|v| *v));
+        assert_send(&cell.with_mut_async(|v| *v));
     }
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
     #[test]
@@ -567,6 +1438,489 @@ mod tests {
         assert_eq!(new_len, 4);
     }
 
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_try_with() {
+        let cell = SyncCell::new(1);
+        assert_eq!(cell.try_with(|v| *v), Ok(1));
+        assert_eq!(cell.try_with_mut(|v| *v += 1), Ok(()));
+        assert_eq!(cell.into_inner(), 2);
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_try_with_reentrant() {
+        use std::time::Duration;
+
+        let cell = SyncCell::new(1);
+        assert_eq!(
+            cell.with(|_| cell.try_with(|v| *v)),
+            Err(TryWithError::Reentrant)
+        );
+        assert_eq!(
+            cell.with_mut(|_| cell.try_with_mut(|v| *v)),
+            Err(TryWithError::Reentrant)
+        );
+        //fails right away rather than waiting out the timeout
+        assert_eq!(
+            cell.with(|_| cell.with_timeout(Duration::from_secs(1000), |v| *v)),
+            Err(TryWithError::Reentrant)
+        );
+        assert!(!cell.is_poisoned());
+    }
+
+    #[test]
+    //note: unwind tests are not supported in wasm
+    fn test_reentrant_with_panics() {
+        let cell = SyncCell::new(1);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cell.with(|_| cell.with_mut(|v| *v += 1))
+        }));
+        let message = result.unwrap_err();
+        assert!(
+            message
+                .downcast_ref::<&str>()
+                .unwrap()
+                .contains("reentrantly")
+        );
+    }
+
+    #[test]
+    fn test_try_with_would_block() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        let cell = SyncCell::new(1);
+        while_locked_elsewhere(&cell, || {
+            assert_eq!(cell.try_with(|v| *v), Err(TryWithError::WouldBlock));
+            assert_eq!(cell.try_with_mut(|v| *v), Err(TryWithError::WouldBlock));
+        });
+    }
+
+    #[test]
+    fn test_with_timeout() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        use std::sync::{Arc, Barrier};
+        use std::time::Duration;
+
+        let cell = Arc::new(SyncCell::new(0));
+        let locked = Arc::new(Barrier::new(2));
+        let release = Arc::new(Barrier::new(2));
+        let holder = {
+            let (cell, locked, release) = (cell.clone(), locked.clone(), release.clone());
+            std::thread::spawn(move || {
+                cell.with_mut(|v| {
+                    *v = 1;
+                    locked.wait();
+                    release.wait();
+                })
+            })
+        };
+
+        locked.wait();
+        assert_eq!(
+            cell.with_timeout(Duration::from_millis(10), |v| *v),
+            Err(TryWithError::TimedOut)
+        );
+        assert_eq!(
+            cell.with_mut_timeout(Duration::ZERO, |v| *v),
+            Err(TryWithError::TimedOut)
+        );
+        release.wait();
+        assert_eq!(cell.with_timeout(Duration::from_secs(10), |v| *v), Ok(1));
+        holder.join().unwrap();
+    }
+
+    /// Runs `f` while another thread holds the cell's lock.
+    fn while_locked_elsewhere<T: Send>(cell: &SyncCell<T>, f: impl FnOnce()) {
+        let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                let _guard = cell.lock.lock().unwrap();
+                locked_tx.send(()).unwrap();
+                let _ = release_rx.recv();
+            });
+            locked_rx.recv().unwrap();
+            f();
+            drop(release_tx);
+        });
+    }
+
+    /// A waker that records whether it was woken.
+    struct FlagWaker(std::sync::atomic::AtomicBool);
+
+    impl std::task::Wake for FlagWaker {
+        fn wake(self: std::sync::Arc<Self>) {
+            self.0.store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    impl FlagWaker {
+        fn new() -> (std::sync::Arc<FlagWaker>, std::task::Waker) {
+            let flag = std::sync::Arc::new(FlagWaker(std::sync::atomic::AtomicBool::new(false)));
+            (flag.clone(), flag.into())
+        }
+
+        fn take(&self) -> bool {
+            self.0.swap(false, std::sync::atomic::Ordering::SeqCst)
+        }
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_with_async_uncontended() {
+        use std::task::{Context, Poll};
+
+        let cell = SyncCell::new(1);
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let mut future = std::pin::pin!(cell.with_mut_async(|v| {
+            *v += 1;
+            *v
+        }));
+        assert_eq!(future.as_mut().poll(&mut cx), Poll::Ready(2));
+    }
+
+    #[test]
+    fn test_with_async_waits_in_order() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        use std::task::{Context, Poll};
+
+        let cell = SyncCell::new(Vec::new());
+        let (flag_a, waker_a) = FlagWaker::new();
+        let (flag_b, waker_b) = FlagWaker::new();
+        let mut a = Box::pin(cell.with_mut_async(|v| v.push("a")));
+        let mut b = Box::pin(cell.with_mut_async(|v| v.push("b")));
+
+        //both queue up while the lock is held
+        while_locked_elsewhere(&cell, || {
+            assert!(
+                a.as_mut()
+                    .poll(&mut Context::from_waker(&waker_a))
+                    .is_pending()
+            );
+            assert!(
+                b.as_mut()
+                    .poll(&mut Context::from_waker(&waker_b))
+                    .is_pending()
+            );
+        });
+
+        //only the front waiter is woken, and b cannot overtake it
+        assert!(flag_a.take());
+        assert!(!flag_b.take());
+        assert!(
+            b.as_mut()
+                .poll(&mut Context::from_waker(&waker_b))
+                .is_pending()
+        );
+        assert_eq!(
+            a.as_mut().poll(&mut Context::from_waker(&waker_a)),
+            Poll::Ready(())
+        );
+
+        assert!(flag_b.take());
+        assert_eq!(
+            b.as_mut().poll(&mut Context::from_waker(&waker_b)),
+            Poll::Ready(())
+        );
+        drop((a, b));
+        assert_eq!(cell.into_inner(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_dropped_async_waiter_passes_turn() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        use std::task::{Context, Poll};
+
+        let cell = SyncCell::new(0);
+        let (_flag_a, waker_a) = FlagWaker::new();
+        let (flag_b, waker_b) = FlagWaker::new();
+        let mut a = Box::pin(cell.with_async(|v| *v));
+        let mut b = Box::pin(cell.with_async(|v| *v));
+
+        while_locked_elsewhere(&cell, || {
+            assert!(
+                a.as_mut()
+                    .poll(&mut Context::from_waker(&waker_a))
+                    .is_pending()
+            );
+            assert!(
+                b.as_mut()
+                    .poll(&mut Context::from_waker(&waker_b))
+                    .is_pending()
+            );
+        });
+
+        //a was woken but is cancelled instead of taking its turn
+        drop(a);
+        assert!(flag_b.take());
+        assert_eq!(
+            b.as_mut().poll(&mut Context::from_waker(&waker_b)),
+            Poll::Ready(0)
+        );
+    }
+
+    #[test]
+    //note: unwind tests are not supported in wasm
+    fn test_poison_recovery() {
+        let cell = SyncCell::new(vec![1]);
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cell.with_mut(|v| {
+                v.push(2);
+                panic!("test panic");
+            })
+        }));
+        assert!(cell.is_poisoned());
+
+        //the value can still be reached
+        assert_eq!(cell.with_ignore_poison(|v| v.len()), 2);
+        match cell.try_with_mut(|v| v.pop()) {
+            Err(TryWithError::Poisoned(e)) => assert_eq!(e.into_inner(), Some(2)),
+            other => panic!("unexpected result: {other:?}"),
+        }
+        assert!(cell.is_poisoned());
+
+        cell.clear_poison();
+        assert!(!cell.is_poisoned());
+        assert_eq!(cell.with(|v| v.clone()), vec![1]);
+        assert_eq!(cell.try_with(|v| v.len()), Ok(1));
+    }
+
+    #[test]
+    //note: unwind tests are not supported in wasm
+    fn test_without_poisoning() {
+        let cell = SyncCell::new_without_poisoning(42);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cell.with(|_| panic!("test panic"));
+        }));
+        assert!(result.is_err());
+        assert!(!cell.is_poisoned());
+        assert_eq!(cell.with(|v| *v), 42);
+    }
+
+    #[test]
+    fn test_wait_until_producer_consumer() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        use std::collections::VecDeque;
+
+        let queue = SyncCell::new(VecDeque::new());
+        let received = std::thread::scope(|scope| {
+            let consumers: Vec<_> = (0..2)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut received = Vec::new();
+                        loop {
+                            match queue.wait_until(|q| q.pop_front()) {
+                                Some(item) => received.push(item),
+                                None => return received,
+                            }
+                        }
+                    })
+                })
+                .collect();
+            for item in 0..100 {
+                queue.with_mut(|q| q.push_back(Some(item)));
+                queue.notify_one();
+            }
+            //one end marker per consumer
+            queue.with_mut(|q| q.extend([None, None]));
+            queue.notify_all();
+            consumers
+                .into_iter()
+                .flat_map(|consumer| consumer.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        let mut received = received;
+        received.sort_unstable();
+        assert_eq!(received, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_wait_until_timeout() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        use std::time::Duration;
+
+        let flag = SyncCell::new(false);
+        assert_eq!(
+            flag.wait_until_timeout(Duration::from_millis(10), |f| f.then_some(())),
+            None
+        );
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                flag.set(true);
+                flag.notify_all();
+            });
+            assert_eq!(
+                flag.wait_until_timeout(Duration::from_secs(60), |f| f.then_some(1)),
+                Some(1)
+            );
+        });
+        //the lock is released after waiting
+        assert_eq!(flag.try_with(|f| *f), Ok(true));
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_exclusive_access_and_conversions() {
+        let mut cell = SyncCell::new(vec![1]);
+        cell.get_mut().push(2);
+        cell.as_mut().push(3);
+
+        let unsafe_cell: UnsafeSyncCell<Vec<i32>> = cell.into();
+        let cell: SyncCell<Vec<i32>> = unsafe_cell.into();
+        let send_cell: SendCell<Vec<i32>> = cell.into();
+        let cell: SyncCell<Vec<i32>> = send_cell.try_into().ok().unwrap();
+        assert_eq!(cell.into_inner(), vec![1, 2, 3]);
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_value_helpers() {
+        let cell = SyncCell::new(vec![1]);
+        assert_eq!(cell.replace(vec![2]), vec![1]);
+        cell.update(|v| v.iter().map(|x| x + 1).collect());
+        assert_eq!(cell.get_cloned(), vec![3]);
+        assert_eq!(cell.take(), vec![3]);
+        assert_eq!(cell.get_cloned(), Vec::<i32>::new());
+        cell.set(vec![4]);
+        assert_eq!(cell.into_inner(), vec![4]);
+
+        let number = SyncCell::new(5u8);
+        number.update(|n| n * 2);
+        assert_eq!(number.get_copy(), 10);
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_swap() {
+        let a = SyncCell::new(String::from("a"));
+        let b = SyncCell::new(String::from("b"));
+        a.swap(&b);
+        //swapping with itself must not deadlock
+        a.swap(&a);
+        assert_eq!(a.into_inner(), "b");
+        assert_eq!(b.into_inner(), "a");
+    }
+
+    #[test]
+    //note: unwind tests are not supported in wasm
+    fn test_swap_with_poisoned_leaves_self_unpoisoned() {
+        let a = SyncCell::new(1);
+        let b = SyncCell::new(2);
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            b.with(|_| panic!("poison"))
+        }));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| a.swap(&b)));
+        assert!(result.is_err());
+        assert!(!a.is_poisoned());
+        assert_eq!(a.into_inner(), 1);
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_replaced_value_dropped_outside_lock() {
+        use std::sync::{Arc, Weak};
+
+        struct UsesCell(Weak<SyncCell<UsesCell>>);
+        impl Drop for UsesCell {
+            fn drop(&mut self) {
+                if let Some(cell) = self.0.upgrade() {
+                    //would be reentrant if the lock were still held
+                    assert!(cell.try_with(|_| ()).is_ok());
+                }
+            }
+        }
+        let cell = Arc::new_cyclic(|weak| SyncCell::new(UsesCell(weak.clone())));
+        cell.set(UsesCell(Weak::new()));
+        cell.replace(UsesCell(Arc::downgrade(&cell)));
+        cell.update(|_| UsesCell(Weak::new()));
+    }
+
+    #[test]
+    fn test_with_all_opposite_orders() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        let a = SyncCell::new(0);
+        let b = SyncCell::new(0);
+        std::thread::scope(|scope| {
+            for reversed in [false, true] {
+                let (a, b) = (&a, &b);
+                scope.spawn(move || {
+                    for _ in 0..1000 {
+                        if reversed {
+                            with_all((b, a), |(b, a)| {
+                                *a += 1;
+                                *b += 1;
+                            });
+                        } else {
+                            with_all((a, b), |(a, b)| {
+                                *a += 1;
+                                *b += 1;
+                            });
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!((a.into_inner(), b.into_inner()), (2000, 2000));
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_with_all_three() {
+        let a = SyncCell::new(vec![1]);
+        let b = SyncCell::new(String::new());
+        let c = SyncCell::new(0u8);
+        (&a, &b, &c).with_all(|(a, b, c)| {
+            b.push_str("moved");
+            *c = a.pop().unwrap();
+        });
+        assert_eq!(a.with(|a| a.len()), 0);
+        assert_eq!(b.into_inner(), "moved");
+        assert_eq!(c.into_inner(), 1);
+    }
+
+    #[test]
+    //note: unwind tests are not supported in wasm
+    fn test_with_all_duplicate_panics() {
+        let a = SyncCell::new(1);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            with_all((&a, &a), |(x, y)| *x + *y)
+        }));
+        assert!(result.is_err());
+        //rejected before locking anything
+        assert!(!a.is_poisoned());
+        assert_eq!(a.try_with(|v| *v), Ok(1));
+    }
+
+    #[test]
+    //note: unwind tests are not supported in wasm
+    fn test_with_all_poisoned_leaves_others_unpoisoned() {
+        let x = SyncCell::new(1);
+        let y = SyncCell::new(2);
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            y.with(|_| panic!("poison"))
+        }));
+        for _ in 0..2 {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                with_all((&x, &y), |(a, b)| *a + *b)
+            }));
+            assert!(result.is_err());
+            assert!(!x.is_poisoned());
+            //the poisoned cell may come first or second in address order
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                with_all((&y, &x), |(a, b)| *a + *b)
+            }));
+            assert!(result.is_err());
+            assert!(!x.is_poisoned());
+        }
+        //a reentrant cell is rejected the same way
+        let z = SyncCell::new(3);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            x.with(|_| with_all((&z, &x), |(a, b)| *a + *b))
+        }));
+        assert!(result.is_err());
+        assert!(!z.is_poisoned());
+    }
+
     //no unwind on wasm!
     #[test]
     //note: unwind tests are not supported in wasm
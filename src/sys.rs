@@ -23,7 +23,9 @@ crate to work correctly in browser environments with Web Workers.
 # Usage
 
 This module is primarily used internally by the send_cells crate to:
-- Get current thread IDs for runtime checking in [`crate::SendCell`]
+- Get cheap per-thread tokens for runtime checking in [`crate::SendCell`], via
+  [`current_thread_token`]
+- Recognize the main thread for [`crate::MainThreadCell`], via [`is_main_thread`]
 - Provide thread-safe abstractions that work across platforms
 - Enable consistent behavior between native and WebAssembly environments
 
@@ -47,3 +49,159 @@ pub use wasm_thread as thread;
 
 #[cfg(not(target_arch = "wasm32"))]
 pub use std::thread;
+
+use std::collections::HashMap;
+use std::num::NonZeroU64;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+/// Returns a number that uniquely identifies the current thread.
+///
+/// This is the identity used by the crate's checked types on their hot paths. Unlike
+/// `thread::current().id()`, which clones a reference-counted handle to the current thread,
+/// it is a single thread-local read. Tokens are allocated from a global counter the first
+/// time a thread asks for one, and are never reused, even after the thread exits.
+///
+/// Tokens are unrelated to [`thread::ThreadId`]; the crate maps them back to their threads
+/// for diagnostics, for as long as the threads are running.
+///
+/// # Examples
+///
+/// ```rust
+/// use send_cells::sys::current_thread_token;
+///
+/// let here = current_thread_token();
+/// assert_eq!(here, current_thread_token());
+/// let there = std::thread::spawn(current_thread_token).join().unwrap();
+/// assert_ne!(here, there);
+/// ```
+#[inline]
+pub fn current_thread_token() -> NonZeroU64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        static TOKEN: NonZeroU64 = {
+            let token = NEXT.fetch_add(1, Ordering::Relaxed);
+            let token = NonZeroU64::new(token).expect("thread token counter overflowed");
+            threads()
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(token, thread::current());
+            //too late to unregister if the thread is already exiting; the entry is then leaked
+            let _ = UNREGISTER.try_with(|_| {});
+            token
+        };
+        static UNREGISTER: Unregister = const { Unregister };
+    }
+    //the token has no destructor, so it stays readable while other thread-locals are destroyed
+    TOKEN.with(|token| *token)
+}
+
+/// The running threads that have a token, so that cells can keep a token instead of a
+/// thread handle.
+fn threads() -> &'static Mutex<HashMap<NonZeroU64, thread::Thread>> {
+    static THREADS: OnceLock<Mutex<HashMap<NonZeroU64, thread::Thread>>> = OnceLock::new();
+    THREADS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Removes the current thread from [`threads`] when it exits.
+struct Unregister;
+
+impl Drop for Unregister {
+    fn drop(&mut self) {
+        let token = current_thread_token();
+        threads()
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&token);
+    }
+}
+
+/// Returns the thread that `token` was handed to, for diagnostics.
+///
+/// Returns `None` once that thread has exited.
+pub(crate) fn thread_for_token(token: NonZeroU64) -> Option<thread::Thread> {
+    threads()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&token)
+        .cloned()
+}
+
+/// The token of the main thread, or 0 until it is known.
+///
+/// Once set, it never changes, since [`crate::MainThreadMarker`]s handed out on the main
+/// thread rely on no other thread ever being the main thread.
+static MAIN_THREAD: AtomicU64 = AtomicU64::new(0);
+
+/// Returns `true` if the current thread is the main thread.
+///
+/// The main thread is the one passed to [`mark_main_thread`]. Until a thread is marked, on
+/// Linux the thread whose id equals the process id (the one that ran `main`) is recognized
+/// automatically, as long as `/proc` is mounted. Elsewhere, this returns `false` until a
+/// thread is marked.
+///
+/// Whichever thread is recognized first stays the main thread for the rest of the process.
+///
+/// # Examples
+///
+/// ```rust
+/// use send_cells::sys::{is_main_thread, mark_main_thread};
+///
+/// mark_main_thread();
+/// assert!(is_main_thread());
+/// assert!(!std::thread::spawn(is_main_thread).join().unwrap());
+/// ```
+pub fn is_main_thread() -> bool {
+    let token = current_thread_token().get();
+    match MAIN_THREAD.load(Ordering::Relaxed) {
+        0 if is_process_main_thread() => {
+            match MAIN_THREAD.compare_exchange(0, token, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => true,
+                //another thread was marked in the meantime
+                Err(main) => main == token,
+            }
+        }
+        main => main == token,
+    }
+}
+
+/// Makes the current thread the main thread, for [`is_main_thread`].
+///
+/// Call this early in `main` on platforms where the main thread is not recognized
+/// automatically, or to treat another thread (such as a UI thread) as the main thread.
+/// Calling it again on the same thread has no effect.
+///
+/// # Panics
+///
+/// Panics if another thread is already the main thread, including because it was
+/// recognized automatically.
+pub fn mark_main_thread() {
+    let token = current_thread_token().get();
+    if let Err(main) = MAIN_THREAD.compare_exchange(0, token, Ordering::Relaxed, Ordering::Relaxed)
+    {
+        assert!(main == token, "another thread is already the main thread");
+    }
+}
+
+/// Returns `true` if the OS considers the current thread the process's initial thread.
+#[cfg(target_os = "linux")]
+fn is_process_main_thread() -> bool {
+    thread_local! {
+        static IS_MAIN: std::cell::Cell<Option<bool>> = const { std::cell::Cell::new(None) };
+    }
+    IS_MAIN.with(|is_main| {
+        *is_main.get().get_or_insert_with(|| {
+            //a link to "<pid>/task/<tid>"; the initial thread's id is the process id
+            std::fs::read_link("/proc/thread-self")
+                .ok()
+                .and_then(|path| path.file_name()?.to_str()?.parse::<u32>().ok())
+                == Some(std::process::id())
+        })
+    })
+}
+
+/// Returns `true` if the OS considers the current thread the process's initial thread.
+#[cfg(not(target_os = "linux"))]
+fn is_process_main_thread() -> bool {
+    false
+}
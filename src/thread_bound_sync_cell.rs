@@ -0,0 +1,243 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+/*!
+A `Send + Sync` cell whose contents may only be touched on the thread that created it.
+
+This module provides [`ThreadBoundSyncCell<T>`], the `Sync` counterpart of [`crate::SendCell`].
+Where [`crate::SyncCell`] requires `T: Send` and lets any thread access the value under a
+mutex, `ThreadBoundSyncCell` is `Send + Sync` for any `T`, but only allows access on its
+origin thread: the handle can be shared widely (for example, in an `Arc`), while the
+contents are only touched at home.
+
+# Thread Safety Model
+
+- All access is through closures, checked at runtime like [`crate::SendCell::get`]
+- On the origin thread, [`ThreadBoundSyncCell::with_mut`] only needs `&self`; overlapping
+  borrows are caught like those of a [`std::cell::RefCell`]
+- Wrong-thread access is reported through the cell's [`crate::ViolationPolicy`]
+- Dropping the cell on the wrong thread is a violation too, unless it was created with
+  [`ThreadBoundSyncCell::new_deferring_drop`]. This matters when the cell is shared in an
+  `Arc`, since the last reference may be released on any thread.
+
+# Examples
+
+```rust
+use send_cells::ThreadBoundSyncCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+let cell = Arc::new(ThreadBoundSyncCell::new_deferring_drop(Rc::new(1)));
+cell.with_mut(|rc| *rc = Rc::new(2));
+
+let shared = cell.clone();
+std::thread::spawn(move || {
+    // Other threads can hold the handle, but not touch the contents
+    assert!(shared.try_with(|rc| **rc).is_err());
+}).join().unwrap();
+
+assert_eq!(cell.with(|rc| **rc), 2);
+```
+*/
+
+use crate::send_cell::{SendCell, WrongThreadError};
+use std::cell::RefCell;
+use std::fmt::{Debug, Formatter};
+
+/// A `Send + Sync` handle to a value that can only be accessed on its origin thread.
+///
+/// See the [module documentation](crate::thread_bound_sync_cell) for details.
+pub struct ThreadBoundSyncCell<T> {
+    inner: SendCell<RefCell<T>>,
+}
+
+// SAFETY: ThreadBoundSyncCell implements Sync for any T, regardless of whether T implements
+// Sync or Send. Every access through `&self` checks that the current thread is the origin
+// thread, so the value is never reached from two threads.
+unsafe impl<T> Sync for ThreadBoundSyncCell<T> {}
+
+impl<T> ThreadBoundSyncCell<T> {
+    /// Creates a new cell bound to the current thread.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::ThreadBoundSyncCell;
+    /// use std::rc::Rc;
+    ///
+    /// let cell = ThreadBoundSyncCell::new(Rc::new(42));
+    /// assert_eq!(cell.with(|rc| **rc), 42);
+    /// ```
+    #[inline]
+    #[track_caller]
+    pub fn new(value: T) -> ThreadBoundSyncCell<T> {
+        ThreadBoundSyncCell {
+            inner: SendCell::new(RefCell::new(value)),
+        }
+    }
+
+    /// Creates a new cell bound to the current thread, deferring a wrong-thread drop to the
+    /// origin thread.
+    ///
+    /// See [`SendCell::new_deferring_drop`].
+    #[inline]
+    #[track_caller]
+    pub fn new_deferring_drop(value: T) -> ThreadBoundSyncCell<T>
+    where
+        T: 'static,
+    {
+        ThreadBoundSyncCell {
+            inner: SendCell::new_deferring_drop(RefCell::new(value)),
+        }
+    }
+
+    /// Accesses the value through a closure.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from a thread other than the one where the cell was created
+    /// (subject to the cell's [`crate::ViolationPolicy`]), or if the value is currently
+    /// borrowed mutably by an enclosing [`Self::with_mut`].
+    #[inline]
+    #[track_caller]
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.inner.get().borrow())
+    }
+
+    /// Accesses the value mutably through a closure.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from a thread other than the one where the cell was created
+    /// (subject to the cell's [`crate::ViolationPolicy`]), or if the value is currently
+    /// borrowed by an enclosing [`Self::with`] or [`Self::with_mut`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use send_cells::ThreadBoundSyncCell;
+    ///
+    /// let cell = ThreadBoundSyncCell::new(vec![1, 2]);
+    /// cell.with_mut(|v| v.push(3));
+    /// assert_eq!(cell.with(|v| v.len()), 3);
+    /// ```
+    #[inline]
+    #[track_caller]
+    pub fn with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut self.inner.get().borrow_mut())
+    }
+
+    /// Accesses the value through a closure, or returns an error if called from the wrong
+    /// thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed mutably by an enclosing [`Self::with_mut`].
+    #[inline]
+    pub fn try_with<R>(&self, f: impl FnOnce(&T) -> R) -> Result<R, WrongThreadError> {
+        self.inner.try_get().map(|cell| f(&cell.borrow()))
+    }
+
+    /// Accesses the value mutably through a closure, or returns an error if called from the
+    /// wrong thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by an enclosing [`Self::with`] or
+    /// [`Self::with_mut`].
+    #[inline]
+    pub fn try_with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, WrongThreadError> {
+        self.inner.try_get().map(|cell| f(&mut cell.borrow_mut()))
+    }
+
+    /// Consumes the cell and returns the value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from a thread other than the one where the cell was created.
+    #[inline]
+    #[track_caller]
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner().into_inner()
+    }
+
+    /// Consumes the cell and returns the value, or returns the cell if called from the
+    /// wrong thread.
+    #[inline]
+    pub fn try_into_inner(self) -> Result<T, ThreadBoundSyncCell<T>> {
+        self.inner
+            .try_into_inner()
+            .map(RefCell::into_inner)
+            .map_err(|inner| ThreadBoundSyncCell { inner })
+    }
+}
+
+impl<T: Debug> Debug for ThreadBoundSyncCell<T> {
+    #[track_caller]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.with(|value| value.fmt(f))
+    }
+}
+
+impl<T: Default> Default for ThreadBoundSyncCell<T> {
+    #[track_caller]
+    fn default() -> Self {
+        ThreadBoundSyncCell::new(T::default())
+    }
+}
+
+impl<T> From<T> for ThreadBoundSyncCell<T> {
+    #[track_caller]
+    fn from(value: T) -> Self {
+        ThreadBoundSyncCell::new(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[test]
+    fn test_with_and_with_mut() {
+        let cell = ThreadBoundSyncCell::new(Rc::new(1));
+        cell.with_mut(|rc| *rc = Rc::new(**rc + 1));
+        assert_eq!(cell.with(|rc| **rc), 2);
+        assert_eq!(*cell.into_inner(), 2);
+    }
+
+    #[test]
+    //note: unwind tests are not supported in wasm
+    fn test_overlapping_mutable_borrow_panics() {
+        let cell = ThreadBoundSyncCell::new(0);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cell.with(|_| cell.with_mut(|v| *v = 1));
+        }));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_shared_across_threads() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        let cell = Arc::new(ThreadBoundSyncCell::new(Rc::new(5)));
+        let shared = cell.clone();
+        std::thread::spawn(move || {
+            assert!(shared.try_with(|rc| **rc).is_err());
+            assert!(shared.try_with_mut(|rc| **rc).is_err());
+            let result =
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| shared.with(|rc| **rc)));
+            assert!(result.is_err());
+        })
+        .join()
+        .unwrap();
+        assert_eq!(cell.with(|rc| **rc), 5);
+    }
+
+    #[test]
+    fn test_last_reference_released_elsewhere_defers_drop() {
+        //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+        let cell = Arc::new(ThreadBoundSyncCell::new_deferring_drop(Rc::new(())));
+        std::thread::spawn(move || drop(cell)).join().unwrap();
+        assert_eq!(crate::drain_pending_drops(), 1);
+    }
+}
@@ -235,7 +235,7 @@ impl<T> UnsafeSyncCell<T> {
     /// println!("{}", value);
     /// ```
     #[inline]
-    pub fn new(value: T) -> Self {
+    pub const fn new(value: T) -> Self {
         UnsafeSyncCell(UnsafeCell::new(value))
     }
     /// Gets a reference to the underlying value.
@@ -0,0 +1,451 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+/*!
+Configurable handling of thread-affinity violations in the checked wrappers.
+
+By default, [`crate::SendCell`] and [`crate::SendFuture`] panic when they are used from
+the wrong thread. A [`ViolationPolicy`] changes that behavior, either for a single cell
+(via [`crate::SendCell::builder`]) or for the whole process (via [`set_violation_policy`]).
+A cell's own policy takes precedence over the process-wide one. [`crate::StickyCell`] has no
+policy of its own and follows the process-wide one when accessed from the wrong thread.
+
+Independently of the policy, a process-wide hook installed with [`set_violation_hook`]
+is called with a [`ViolationReport`] before the policy is applied. This is the place to
+route violations into logging or telemetry.
+
+# Policies
+
+| Policy | `get` / `get_mut` / `into_inner` | `poll` | drop |
+|--------|----------------------------------|--------|------|
+| [`ViolationPolicy::Panic`] | panic | panic | panic |
+| [`ViolationPolicy::Abort`] | abort | abort | abort |
+| [`ViolationPolicy::LeakAndContinue`] | panic | `Poll::Pending` | leak the value |
+| [`ViolationPolicy::CallHook`] | hook, then panic | hook, then `Poll::Pending` | hook, then leak |
+
+Accessors must produce a value, so they cannot continue past a violation and panic under
+every policy except `Abort`. Polling and dropping can continue: a misplaced future simply
+never completes, and a misplaced value is leaked instead of being dropped on the wrong
+thread. This matters most for drop, where a panic during unwinding aborts the process.
+
+# Examples
+
+```rust
+use send_cells::{SendCell, ViolationPolicy};
+use std::rc::Rc;
+
+let cell = SendCell::builder(Rc::new(42))
+    .policy(ViolationPolicy::LeakAndContinue)
+    .build();
+
+// Dropping on the wrong thread leaks the Rc instead of panicking
+std::thread::spawn(move || drop(cell)).join().unwrap();
+```
+*/
+
+use crate::sys::thread::{Thread, ThreadId};
+#[cfg(feature = "backtrace")]
+use std::backtrace::Backtrace;
+use std::fmt::{Display, Formatter};
+use std::panic::Location;
+#[cfg(feature = "backtrace")]
+use std::sync::Arc;
+use std::sync::RwLock;
+
+/// The operation that was attempted on the wrong thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    /// [`crate::SendCell::get`] or another shared access.
+    Get,
+    /// [`crate::SendCell::get_mut`] or another exclusive access.
+    GetMut,
+    /// [`crate::SendCell::into_inner`].
+    IntoInner,
+    /// Polling a [`crate::SendFuture`].
+    Poll,
+    /// Dropping the wrapped value.
+    Drop,
+}
+
+/// What a checked wrapper does when it is used from the wrong thread.
+///
+/// See the [module documentation](self) for how each policy applies to each operation.
+#[derive(Debug, Clone, Copy)]
+pub enum ViolationPolicy {
+    /// Panic. This is the default.
+    Panic,
+    /// Print the violation to stderr and abort the process.
+    Abort,
+    /// Leak the value (or leave the future pending) and continue where possible.
+    LeakAndContinue,
+    /// Call the given function, then continue as for [`Self::LeakAndContinue`].
+    CallHook(fn(&ViolationReport)),
+}
+
+/// A description of a thread-affinity violation.
+///
+/// Passed to the hook installed with [`set_violation_hook`] and to
+/// [`ViolationPolicy::CallHook`].
+#[derive(Debug, Clone)]
+pub struct ViolationReport {
+    operation: Operation,
+    type_name: &'static str,
+    //None if unknown; see `owner`
+    owner: Option<Thread>,
+    current: Thread,
+    created_at: &'static Location<'static>,
+    location: Option<&'static Location<'static>>,
+    //owner and current context, for cells with a custom identity
+    contexts: Option<(String, String)>,
+    //the checked type reporting the violation
+    wrapper: &'static str,
+    #[cfg(feature = "backtrace")]
+    backtrace: Arc<Backtrace>,
+}
+
+impl ViolationReport {
+    pub(crate) fn new(
+        operation: Operation,
+        type_name: &'static str,
+        owner: Option<Thread>,
+        current: Thread,
+        created_at: &'static Location<'static>,
+        location: Option<&'static Location<'static>>,
+        #[cfg(feature = "backtrace")] backtrace: Arc<Backtrace>,
+    ) -> Self {
+        ViolationReport {
+            operation,
+            type_name,
+            owner,
+            current,
+            created_at,
+            location,
+            contexts: None,
+            wrapper: "SendCell",
+            #[cfg(feature = "backtrace")]
+            backtrace,
+        }
+    }
+
+    pub(crate) fn with_wrapper(mut self, wrapper: &'static str) -> Self {
+        self.wrapper = wrapper;
+        self
+    }
+
+    pub(crate) fn with_contexts(mut self, owner: String, current: String) -> Self {
+        self.contexts = Some((owner, current));
+        self
+    }
+
+    /// Returns the operation that was attempted.
+    pub fn operation(&self) -> Operation {
+        self.operation
+    }
+
+    /// Returns the name of the wrapped type.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// Returns the id of the thread the value belongs to.
+    ///
+    /// Cells only keep a cheap token for their thread, so this is `None` once that thread
+    /// has exited. It is also `None` for cells bound to a custom [`crate::ThreadIdentity`];
+    /// see [`Self::contexts`].
+    pub fn owner(&self) -> Option<ThreadId> {
+        self.owner.as_ref().map(Thread::id)
+    }
+
+    /// Returns the id of the thread that attempted the operation.
+    pub fn current(&self) -> ThreadId {
+        self.current.id()
+    }
+
+    /// Returns the name of the thread the value belongs to, if it is known and has one.
+    pub fn owner_name(&self) -> Option<&str> {
+        self.owner.as_ref()?.name()
+    }
+
+    /// Returns the name of the thread that attempted the operation, if it has one.
+    pub fn current_name(&self) -> Option<&str> {
+        self.current.name()
+    }
+
+    /// Returns the source location where the cell was created.
+    pub fn created_at(&self) -> &'static Location<'static> {
+        self.created_at
+    }
+
+    /// Returns the source location of the offending call.
+    ///
+    /// This is `None` for [`Operation::Poll`] and [`Operation::Drop`], which are
+    /// invoked by an executor or by drop glue rather than at a meaningful call site.
+    pub fn location(&self) -> Option<&'static Location<'static>> {
+        self.location
+    }
+
+    /// Returns the `Debug` representations of the owning and the current context, for cells
+    /// bound with a custom [`crate::ThreadIdentity`].
+    ///
+    /// Such contexts can share an OS thread, or move between OS threads, so they are reported
+    /// instead of [`Self::owner`]. Returns `None` for cells bound to an OS thread.
+    pub fn contexts(&self) -> Option<(&str, &str)> {
+        self.contexts
+            .as_ref()
+            .map(|(owner, current)| (owner.as_str(), current.as_str()))
+    }
+
+    /// Returns the backtrace captured when the cell was created.
+    ///
+    /// Capturing follows the rules of [`Backtrace::capture`], so the backtrace is
+    /// only populated when `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` is set.
+    #[cfg(feature = "backtrace")]
+    pub fn creation_backtrace(&self) -> &Backtrace {
+        &self.backtrace
+    }
+}
+
+/// Formats a thread as its name (if any) followed by its id.
+struct DescribeThread<'a>(&'a Thread);
+
+impl Display for DescribeThread<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.0.name() {
+            Some(name) => write!(f, "{name:?} ({:?})", self.0.id()),
+            None => write!(f, "<unnamed> ({:?})", self.0.id()),
+        }
+    }
+}
+
+impl Display for ViolationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.operation {
+            Operation::Get | Operation::GetMut | Operation::IntoInner => {
+                write!(f, "Access {} from incorrect thread", self.wrapper)?
+            }
+            Operation::Poll => write!(f, "SendFuture polled from incorrect thread")?,
+            Operation::Drop => write!(f, "Drop {} from incorrect thread", self.wrapper)?,
+        }
+        match (&self.contexts, &self.owner) {
+            (Some((owner, current)), _) => write!(
+                f,
+                ": {} belongs to context {owner} but was used in context {current} on thread {}",
+                self.type_name,
+                DescribeThread(&self.current),
+            )?,
+            (None, Some(owner)) => write!(
+                f,
+                ": {} belongs to thread {} but was used on thread {}",
+                self.type_name,
+                DescribeThread(owner),
+                DescribeThread(&self.current),
+            )?,
+            (None, None) => write!(
+                f,
+                ": {} belongs to a thread that has exited but was used on thread {}",
+                self.type_name,
+                DescribeThread(&self.current),
+            )?,
+        }
+        write!(f, "; created at {}", self.created_at)?;
+        if let Some(location) = self.location {
+            write!(f, ", used at {location}")?;
+        }
+        #[cfg(feature = "backtrace")]
+        if self.backtrace.status() == std::backtrace::BacktraceStatus::Captured {
+            write!(f, "\ncreation backtrace:\n{}", self.backtrace)?;
+        }
+        Ok(())
+    }
+}
+
+static GLOBAL_POLICY: RwLock<ViolationPolicy> = RwLock::new(ViolationPolicy::Panic);
+static GLOBAL_HOOK: RwLock<Option<fn(&ViolationReport)>> = RwLock::new(None);
+
+/// Sets the process-wide policy used by cells that do not specify their own.
+///
+/// # Examples
+///
+/// ```rust
+/// use send_cells::{ViolationPolicy, set_violation_policy, violation_policy};
+///
+/// set_violation_policy(ViolationPolicy::LeakAndContinue);
+/// assert!(matches!(violation_policy(), ViolationPolicy::LeakAndContinue));
+/// # set_violation_policy(ViolationPolicy::Panic);
+/// ```
+pub fn set_violation_policy(policy: ViolationPolicy) {
+    *GLOBAL_POLICY.write().unwrap_or_else(|e| e.into_inner()) = policy;
+}
+
+/// Returns the process-wide policy used by cells that do not specify their own.
+pub fn violation_policy() -> ViolationPolicy {
+    *GLOBAL_POLICY.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Installs a process-wide hook that is called for every violation.
+///
+/// The hook runs before the violation policy is applied, so it also sees violations
+/// that go on to panic or abort. It replaces any previously installed hook. The
+/// non-panicking `try_*` accessors do not count as violations and do not call the hook.
+///
+/// # Examples
+///
+/// ```rust
+/// use send_cells::{SendCell, ViolationReport, set_violation_hook};
+///
+/// fn log_violation(report: &ViolationReport) {
+///     eprintln!(
+///         "{:?} of {} from {:?} (created at {})",
+///         report.operation(),
+///         report.type_name(),
+///         report.current_name(),
+///         report.created_at()
+///     );
+/// }
+/// set_violation_hook(log_violation);
+///
+/// let cell = SendCell::new(42);
+/// let result = std::thread::spawn(move || *cell.get()).join();
+/// assert!(result.is_err());
+/// # send_cells::clear_violation_hook();
+/// ```
+pub fn set_violation_hook(hook: fn(&ViolationReport)) {
+    *GLOBAL_HOOK.write().unwrap_or_else(|e| e.into_inner()) = Some(hook);
+}
+
+/// Removes the hook installed with [`set_violation_hook`], if any.
+pub fn clear_violation_hook() {
+    *GLOBAL_HOOK.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Calls the global hook, then applies the policy to a violation.
+///
+/// Returns only if the policy allows the operation to continue; the caller is then
+/// responsible for leaking the value or otherwise not touching it.
+#[cold]
+pub(crate) fn handle(policy: Option<ViolationPolicy>, report: &ViolationReport) {
+    let hook = *GLOBAL_HOOK.read().unwrap_or_else(|e| e.into_inner());
+    if let Some(hook) = hook {
+        hook(report);
+    }
+    match policy.unwrap_or_else(violation_policy) {
+        ViolationPolicy::Panic => panic!("{report}"),
+        ViolationPolicy::Abort => {
+            eprintln!("{report}");
+            std::process::abort()
+        }
+        ViolationPolicy::LeakAndContinue => {}
+        ViolationPolicy::CallHook(hook) => hook(report),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SendCell;
+    use crate::sys::thread;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+    fn test_leak_on_wrong_thread_drop() {
+        let data = Rc::new(42);
+        let cell = SendCell::builder(data.clone())
+            .policy(ViolationPolicy::LeakAndContinue)
+            .build();
+        thread::spawn(move || drop(cell)).join().unwrap();
+        // The clone inside the cell was leaked, not dropped
+        assert_eq!(Rc::strong_count(&data), 2);
+    }
+
+    #[test]
+    //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+    fn test_leak_still_panics_on_access() {
+        let cell = SendCell::builder(42)
+            .policy(ViolationPolicy::LeakAndContinue)
+            .build();
+        let result = thread::spawn(move || {
+            let _ = *cell.get();
+        })
+        .join();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+    fn test_global_hook_report() {
+        use std::sync::Mutex;
+
+        struct HookMarker;
+        static REPORTS: Mutex<Vec<ViolationReport>> = Mutex::new(Vec::new());
+        fn hook(report: &ViolationReport) {
+            if report.type_name().ends_with("HookMarker") {
+                REPORTS.lock().unwrap().push(report.clone());
+            }
+        }
+        set_violation_hook(hook);
+
+        let created_line = line!() + 1;
+        let cell = SendCell::new(HookMarker);
+        let (access_line, result) = thread::Builder::new()
+            .name("offender".to_string())
+            .spawn(move || {
+                let line = line!() + 2;
+                let result = std::panic::catch_unwind(|| {
+                    let _ = cell.get();
+                });
+                (line, result.is_err())
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        clear_violation_hook();
+        assert!(result);
+
+        let reports = REPORTS.lock().unwrap();
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.operation(), Operation::Get);
+        assert_eq!(report.owner(), Some(thread::current().id()));
+        assert_eq!(report.owner_name(), thread::current().name());
+        assert_eq!(report.current_name(), Some("offender"));
+        assert_eq!(report.created_at().file(), file!());
+        assert_eq!(report.created_at().line(), created_line);
+        assert_eq!(report.location().unwrap().line(), access_line);
+    }
+
+    #[test]
+    //at the moment, threads don't work in node: https://github.com/wasm-bindgen/wasm-bindgen/issues/4534
+    fn test_hook_called_on_drop_and_poll() {
+        use std::future::Future;
+        use std::pin::Pin;
+        use std::task::{Context, Poll, Waker};
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        fn hook(report: &ViolationReport) {
+            assert!(matches!(
+                report.operation(),
+                Operation::Poll | Operation::Drop
+            ));
+            assert_ne!(report.owner(), Some(report.current()));
+            CALLS.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let cell = SendCell::builder(Rc::new(42))
+            .policy(ViolationPolicy::CallHook(hook))
+            .build();
+        let future = SendCell::builder(std::future::ready(Rc::new(42)))
+            .policy(ViolationPolicy::CallHook(hook))
+            .build()
+            .into_future();
+        thread::spawn(move || {
+            let mut future = Box::pin(future);
+            let mut context = Context::from_waker(Waker::noop());
+            assert_eq!(Pin::new(&mut future).poll(&mut context), Poll::Pending);
+            drop(cell);
+        })
+        .join()
+        .unwrap();
+        // cell drop, future poll, future drop
+        assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+    }
+}